@@ -0,0 +1,58 @@
+//! Bytes prepared for `write_region` while drawing a stroke across a large layer,
+//! with one upload per pixel like drawing with the mouse, against uploading
+//! the whole layer every time.
+//!
+//! `cargo bench --bench upload`
+//!
+//! Only the layer side is timed: the texture upload itself needs a GL context.
+#![allow(dead_code)]
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use nightmare::pixels::Pixel;
+
+// The crate has no library target, so the layer is pulled in by path.
+mod document {
+    #[path = "../../src/document/layer.rs"]
+    pub mod layer;
+    #[path = "../../src/document/region.rs"]
+    pub mod region;
+}
+
+use document::layer::Layer;
+use document::region::Region;
+
+const SIZE: usize = 1024;
+const STROKE: usize = 200;
+const RUNS: u32 = 20;
+
+/// Average time of a stroke, uploading the region returned by `upload`
+fn stroke(upload: impl Fn(&mut Layer) -> Option<Region>) -> Duration {
+    let mut layer = Layer::new(SIZE, SIZE);
+    layer.take_dirty();
+
+    let now = Instant::now();
+    for _ in 0..RUNS {
+        for i in 0..STROKE {
+            layer.set_pixel(i as i32, i as i32, Pixel::black());
+            if let Some(region) = upload(&mut layer) {
+                black_box(layer.bytes(region));
+            }
+        }
+    }
+
+    now.elapsed() / RUNS
+}
+
+fn main() {
+    let dirty = stroke(|layer| layer.take_dirty());
+    let full = stroke(|layer| {
+        layer.take_dirty();
+        Some(Region::new(0, 0, SIZE, SIZE))
+    });
+
+    println!("{} pixel stroke on {}x{}", STROKE, SIZE, SIZE);
+    println!("dirty regions: {:?}", dirty);
+    println!("whole layer:   {:?}", full);
+}
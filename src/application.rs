@@ -1,35 +1,32 @@
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use log::{error, info};
 use nightmare::events::{Key, Modifiers};
 use nightmare::pixels::Pixel;
 use nightmare::render2d::{Model, SimpleRenderer};
-use nightmare::texture::Texture;
 use nightmare::{Context, Position, Size, Viewport};
 
-// use crate::border::{BorderType, Textures};
-// use crate::canvas::Canvas;
-use crate::commandline::{Command, CommandLine};
-use crate::config::Config;
+use crate::border::Textures;
+use crate::canvas::Canvas;
+use crate::commandline::{script, Command, CommandLine};
+use crate::config::{Action, Config, ConfigErrors};
 // use crate::console::Console;
 use crate::input::{Input, InputToAction};
 use crate::listener::{Listener, MessageCtx};
 use crate::message::Message;
 use crate::mouse::MouseCursor;
-// use crate::status::Status;
+use crate::status::Status;
 
-const VIEWPORT_PADDING: f32 = 128.0;
-
-fn canvas_viewport(viewport: &Viewport) -> Viewport {
-    let pad = VIEWPORT_PADDING;
+fn canvas_viewport(viewport: &Viewport, pad: f32) -> Viewport {
     Viewport::new(Position::new(pad, pad), *viewport.size() - Size::new(pad * 2.0, pad * 2.0))
 }
 
 // -----------------------------------------------------------------------------
 //     - Mode -
 // -----------------------------------------------------------------------------
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Mode {
     Normal,
     Insert,
@@ -48,8 +45,10 @@ pub struct App {
     listeners: Vec<Box<dyn Listener>>,
     app_viewport: Viewport,
     canvas_viewport: Viewport,
-    // textures: Textures,
+    border_textures: Textures,
     renderer: SimpleRenderer<Model>,
+    /// Number of scripts being sourced
+    source_depth: usize,
 }
 
 impl App {
@@ -59,24 +58,12 @@ impl App {
         // -----------------------------------------------------------------------------
         //     - Border textures -
         // -----------------------------------------------------------------------------
-        // let textures = {
-        //     let mut textures = Textures::new();
-
-        //     let canvas = Texture::from_disk("border-canvas.png")?;
-        //     let active = Texture::from_disk("border-active.png")?;
-        //     let inactive = Texture::from_disk("border-inactive.png")?;
-
-        //     textures.insert(BorderType::Canvas, canvas);
-        //     textures.insert(BorderType::Active, active);
-        //     textures.insert(BorderType::Inactive, inactive);
-
-        //     textures
-        // };
+        let textures = Textures::load()?;
 
         // -----------------------------------------------------------------------------
         //     - Canvas viewport -
         // -----------------------------------------------------------------------------
-        let canvas_viewport = canvas_viewport(&app_viewport);
+        let canvas_viewport = canvas_viewport(&app_viewport, config.ui.padding);
         let renderer = SimpleRenderer::new(context, app_viewport.view_projection())?;
 
         let mut inst = Self {
@@ -87,22 +74,22 @@ impl App {
             listeners: vec![],
             app_viewport,
             canvas_viewport,
-            // textures,
+            border_textures: textures,
             renderer,
+            source_depth: 0,
         };
 
         let mut ctx = MessageCtx {
             config: &inst.config,
             canvas_viewport: &inst.canvas_viewport,
             app_viewport: &inst.app_viewport,
-            // textures: &inst.textures,
-            // border_renderer: &inst.renderer,
+            border_textures: &inst.border_textures,
             context,
         };
 
-        // inst.listeners.push(Box::new(Canvas::new(inst.canvas_viewport.clone(), &mut ctx)?));
-        // inst.listeners.push(Box::new(Status::new(win_size, ctx.context)?));
-        inst.listeners.push(Box::new(CommandLine::new(win_size, ctx.context)?));
+        inst.listeners.push(Box::new(Canvas::new(inst.canvas_viewport.clone(), &mut ctx)?));
+        inst.listeners.push(Box::new(Status::new(win_size, &ctx.config.ui, ctx.context)?));
+        inst.listeners.push(Box::new(CommandLine::new(win_size, &ctx.config.ui, ctx.context)?));
         inst.listeners.push(Box::new(MouseCursor::new(&mut ctx)?));
         inst.listeners.push(Box::new(InputToAction::new(inst.mode)));
         // inst.listeners.push(Box::new(Console::new(&mut ctx)?));
@@ -113,7 +100,7 @@ impl App {
     pub fn resize(&mut self, new_size: Size, context: &mut Context) {
         self.win_size = new_size;
         self.app_viewport.resize(new_size);
-        self.canvas_viewport = canvas_viewport(&self.app_viewport);
+        self.canvas_viewport = canvas_viewport(&self.app_viewport, self.config.ui.padding);
         self.handle_messages(Message::Resize(new_size), context);
     }
 
@@ -122,10 +109,6 @@ impl App {
             (Mode::Insert, Input::Key(Key::Escape)) => Some(Mode::Normal),
             (Mode::Visual, Input::Key(Key::Escape)) => Some(Mode::Normal),
             (Mode::Command, Input::Key(Key::Escape)) => Some(Mode::Normal),
-            (Mode::Normal, Input::Char(':')) => Some(Mode::Command),
-            (Mode::Normal, Input::Char('i')) if modifiers.is_empty() => Some(Mode::Insert),
-            (Mode::Visual, Input::Char('i')) if modifiers.is_empty() => Some(Mode::Insert),
-            (Mode::Normal, Input::Char('v')) if modifiers.is_empty() => Some(Mode::Visual),
             _ => None,
         };
 
@@ -156,13 +139,21 @@ impl App {
         Ok(())
     }
 
+    pub fn clear_colour(&self) -> Pixel {
+        self.config.ui.clear_colour.into()
+    }
+
+    /// Called once per frame, before rendering
+    pub fn tick(&mut self, context: &mut Context) {
+        self.handle_messages(Message::Tick, context);
+    }
+
     pub fn render(&mut self, context: &mut Context) {
         let mut ctx = MessageCtx {
             config: &self.config,
             canvas_viewport: &self.canvas_viewport,
             app_viewport: &self.app_viewport,
-            // border_textures: &self.textures,
-            // border_renderer: &self.border_renderer,
+            border_textures: &self.border_textures,
             context,
         };
 
@@ -176,7 +167,47 @@ impl App {
         self.handle_messages(Message::ReloadPlugin(path), context);
     }
 
-    fn handle_messages(&mut self, m: Message, context: &mut Context) {
+    /// Load the config again and swap it in.
+    /// If the new config fails to load the current config is kept.
+    pub fn reload_config(&mut self, context: &mut Context) {
+        match Config::load() {
+            Ok(config) => {
+                info!("Config reloaded");
+                self.config = config;
+                self.canvas_viewport = canvas_viewport(&self.app_viewport, self.config.ui.padding);
+                self.handle_messages(Message::ConfigReloaded, context);
+            }
+            Err(e) => {
+                error!("Failed to reload config: {}", e);
+
+                // Shown in place of the command line, like a command that failed
+                let errors = match e.downcast_ref::<ConfigErrors>() {
+                    Some(errors) => errors.0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "),
+                    None => e.to_string(),
+                };
+
+                let error = format!("Failed to reload config: {}", errors);
+                self.handle_messages(Message::CommandError(error), context);
+            }
+        }
+    }
+
+    /// Run every command in a script file, in order.
+    /// Stops at the first command that fails, with the file and line number in the error.
+    pub fn source(&mut self, path: &Path, context: &mut Context) -> Result<()> {
+        script::source(&mut ScriptSink { app: self, context }, path)
+    }
+
+    /// Source a script from `:source` or `--script`, and show the error if it fails
+    pub fn run_script(&mut self, path: &Path, context: &mut Context) {
+        if let Err(e) = self.source(path, context) {
+            error!("{}", e);
+            self.handle_messages(Message::CommandError(e.to_string()), context);
+        }
+    }
+
+    /// Returns the first command error, if any
+    fn handle_messages(&mut self, m: Message, context: &mut Context) -> Option<String> {
         let mut messages = VecDeque::new();
         messages.push_back(m);
 
@@ -184,22 +215,81 @@ impl App {
             config: &self.config,
             canvas_viewport: &self.canvas_viewport,
             app_viewport: &self.app_viewport,
-            // border_textures: &self.textures,
-            // border_renderer: &self.border_renderer,
+            border_textures: &self.border_textures,
             context,
         };
 
         // Quit?
         let close = &mut self.close;
+        let mode = &mut self.mode;
+        // Scripts are run once every message is handled
+        let mut sources = Vec::new();
+        let mut command_error = None;
 
         while let Some(m) = messages.pop_front() {
+            if let Message::CommandError(e) = &m {
+                command_error.get_or_insert_with(|| e.clone());
+            }
+
             for l in self.listeners.iter_mut() {
                 match l.message(&m, &mut ctx) {
                     Message::Noop => {}
                     Message::Command(Command::Quit) => *close = true,
+                    Message::Command(Command::Source(path)) => sources.push(path),
+                    Message::Command(Command::Batch(commands)) => {
+                        for command in commands {
+                            match command {
+                                Command::Quit => *close = true,
+                                Command::Source(path) => sources.push(path),
+                                command => messages.push_back(Message::Command(command)),
+                            }
+                        }
+                    }
+                    Message::ModeChanged(new_mode) => {
+                        *mode = new_mode;
+                        messages.push_back(Message::ModeChanged(new_mode));
+                    }
+                    Message::RepeatAction(action, count) => {
+                        (0..count).for_each(|_| messages.push_back(Message::Action(action.clone())));
+                    }
                     msg => messages.push_back(msg),
                 }
             }
         }
+
+        for path in sources {
+            match self.source_depth {
+                0 => self.run_script(&path, context),
+                _ => {
+                    if let Err(e) = self.source(&path, context) {
+                        return Some(e.to_string());
+                    }
+                }
+            }
+        }
+
+        command_error
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Script sink -
+//     Runs script commands as if they were typed on the command line
+// -----------------------------------------------------------------------------
+struct ScriptSink<'a, 'b> {
+    app: &'a mut App,
+    context: &'b mut Context,
+}
+
+impl script::Sink for ScriptSink<'_, '_> {
+    fn run_command(&mut self, line: String) -> Result<()> {
+        match self.app.handle_messages(Message::Action(Action::Command(line)), self.context) {
+            Some(e) => Err(anyhow!(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn depth(&mut self) -> &mut usize {
+        &mut self.app.source_depth
     }
 }
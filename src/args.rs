@@ -0,0 +1,58 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+const USAGE: &str = "\
+Usage: mixel [options]
+
+Options:
+    --check-config    Validate the config files and exit
+    --script <file>   Run the commands in <file> once the editor has started
+    --headless        Run the --script without opening a window, then exit
+    -h, --help        Show this message
+";
+
+// -----------------------------------------------------------------------------
+//     - Args -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct Args {
+    pub check_config: bool,
+    pub script: Option<PathBuf>,
+    pub headless: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn from_env() -> Result<Self> {
+        Self::parse(env::args().skip(1))
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut inst = Self::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--check-config" => inst.check_config = true,
+                "--headless" => inst.headless = true,
+                "--script" => match args.next() {
+                    Some(path) => inst.script = Some(path.into()),
+                    None => bail!("--script needs a file\n\n{}", USAGE),
+                },
+                "-h" | "--help" => inst.help = true,
+                arg => bail!("Unknown argument \"{}\"\n\n{}", arg, USAGE),
+            }
+        }
+
+        if inst.headless && inst.script.is_none() {
+            bail!("--headless needs a --script to run\n\n{}", USAGE);
+        }
+
+        Ok(inst)
+    }
+
+    pub fn usage() -> &'static str {
+        USAGE
+    }
+}
@@ -3,9 +3,10 @@ use std::ops::{Deref, DerefMut};
 
 use anyhow::Result;
 use nightmare::texture::Texture;
-use nightmare::{Size, Viewport, VertexData, Context, Transform};
+use nightmare::{Size, Viewport, Context};
 use nightmare::render2d::{SimpleRenderer, Model};
 
+use crate::document::read_png;
 use crate::Node;
 
 const BORDER_ZINDEX: i32 = 999;
@@ -29,6 +30,21 @@ impl Textures {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
+
+    /// The border textures built into the binary
+    pub fn load() -> Result<Self> {
+        let mut textures = Self::new();
+        textures.insert(BorderType::Canvas, texture(include_bytes!("../border-canvas.png"))?);
+        textures.insert(BorderType::Active, texture(include_bytes!("../border-active.png"))?);
+        textures.insert(BorderType::Inactive, texture(include_bytes!("../border-inactive.png"))?);
+        Ok(textures)
+    }
+}
+
+fn texture(png: &[u8]) -> Result<Texture> {
+    let (width, height, pixels) = read_png(png)?;
+    let bytes = pixels.iter().flat_map(|p| vec![p.r, p.g, p.b, p.a]).collect::<Vec<u8>>();
+    Ok(Texture::default_with_data(Size::new(width as f32, height as f32), &bytes))
 }
 
 impl Deref for Textures {
@@ -96,31 +112,20 @@ impl Border {
         self.top.transform.translation.y = viewport.size().height - 4;
     }
 
-    fn vertex_data(&self, parent: &Transform) -> [VertexData; 4] {
+    fn models(&self) -> [Model; 4] {
         [
-            self.top.relative_vertex_data(parent),
-            self.right.relative_vertex_data(parent),
-            self.bottom.relative_vertex_data(parent),
-            self.left.relative_vertex_data(parent),
+            self.top.model(),
+            self.right.model(),
+            self.bottom.model(),
+            self.left.model(),
         ]
     }
 
-    pub fn render(
-        &self,
-        parent: &Transform,
-        textures: &Textures,
-        viewport: &Viewport,
-        renderer: &SimpleRenderer<Model>,
-        context: &mut Context,
-    ) -> Result<()> {
-        let texture = &textures[&self.border_type];
-        renderer.render(
-            texture,
-            &self.vertex_data(parent),
-            viewport,
-            context,
-        )?;
-
-        Ok(())
+    /// Render with the renderer of the viewport the border goes around
+    pub fn render(&self, textures: &Textures, renderer: &SimpleRenderer<Model>, context: &mut Context) {
+        let models = self.models();
+        textures[&self.border_type].bind();
+        renderer.load_data(&models, context);
+        renderer.render_instanced(context, models.len());
     }
 }
@@ -4,19 +4,18 @@
 //! * border
 //! * position
 //! * cursor
-use std::convert::TryInto;
-
 use anyhow::Result;
 use nightmare::pixels::Pixel;
 use nightmare::texture::Texture;
-use nightmare::{Position, Size, Sprite, Transform, Vector, VertexData, Viewport, create_model_matrix};
-use nightmare::text::{Text, WordWrap};
+use nightmare::{Position, Size, Sprite, Transform, Vector, Viewport, create_model_matrix};
 use nightmare::render2d::{SimpleRenderer, Model};
 
 use crate::border::{Border, BorderType};
+use crate::config::Ui;
 use crate::listener::MessageCtx;
 
-use super::{Cursor, Image, Coords};
+use super::{grid, grid_cell, Cursor, Coords, Grid, Line};
+use crate::document::{Image, Reference};
 use crate::layout::Split;
 use crate::Node;
 
@@ -30,8 +29,15 @@ pub struct Container {
     pub viewport: Viewport,
     pub node: Node,
     pub image_id: Option<usize>,
-    pub colour: Pixel,
     pub(super) scale: u32,
+    /// Draw the image in a 3x3 grid of copies, to check that it tiles
+    pub tile_preview: bool,
+    pub show_grid: bool,
+    /// Lines between pixels and tiles, drawn over the image
+    grid: Option<Grid>,
+    /// Image width, height, grid cell and whether the pixel lines are shown,
+    /// for the grid that was made
+    grid_key: (usize, usize, u32, bool),
     pub container_id: usize,
 
     dir: Split,
@@ -47,46 +53,37 @@ impl Container {
         ctx: &mut MessageCtx,
         sprite: Sprite,
     ) -> Result<Self> {
-
         let border_type = BorderType::Inactive;
-
-        let font_size = 22.0;
-
         let renderer = SimpleRenderer::new(ctx.context, viewport.view_projection())?;
 
         let mut inst = Self {
-            border: Border::new(border_type, ctx.textures, &viewport),
+            border: Border::new(border_type, ctx.border_textures, &viewport),
             viewport,
             renderer,
             node: Node::from_sprite(sprite),
             dir,
+            container_id,
             image_id: None,
-            cursor: Cursor::new(Coords::zeros(), sprite.anchor),
-            colour: Pixel::black(),
+            cursor: Cursor::new(Coords::zero(), sprite.anchor),
             scale: 8,
+            tile_preview: false,
+            show_grid: ctx.config.ui.grid,
+            grid: None,
+            grid_key: (0, 0, 0, false),
         };
 
-        // Centre the canvas.
-        let pos = (*inst.viewport.size() / 2).cast();
-        let mut transform = Transform::new(pos.to_vector());
-        transform.scale_mut(inst.scale);
-        inst.node.transform = transform;
-
-        inst.text.set_text(format!("id: {}", inst.container_id));
+        inst.centre();
 
         Ok(inst)
     }
 
+    /// The cursor coords moved by `coords`, without moving the cursor
     pub fn move_cursor_by(&mut self, coords: Coords) -> Coords {
-        let translation = self.cursor.node.transform.translation;
-        let height = self.node.sprite.size.height - 1;
-        let mut current_coords = Coords::from_translation(translation, height);
-        current_coords + coords
+        self.cursor.coords + coords
     }
 
     pub fn move_cursor(&mut self, coords: Coords) {
-        let height = self.node.sprite.size.height - 1;
-        self.cursor.node.transform.translate_mut(coords.to_translation(height));
+        self.cursor.coords = coords;
     }
 
     pub fn render(
@@ -94,95 +91,220 @@ impl Container {
         background_texture: &Texture,
         ctx: &mut MessageCtx,
         image: &Image,
+        textures: &[Texture],
+        reference: Option<(&Reference, &Texture)>,
     ) -> Result<()> {
         // Border
-        // self.border.render(
-        //     &Transform::default(),
-        //     ctx.textures,
-        //     &self.viewport,
-        //     ctx.border_renderer,
-        //     ctx.context,
-        // )?;
+        self.border.render(ctx.border_textures, &self.renderer, ctx.context);
+
+        let transform = self.node.transform;
+        for offset in self.tile_offsets() {
+            let mut transform = transform;
+            transform.translate_mut(transform.translation + offset);
+            self.render_image(background_texture, ctx, image, textures, reference, &transform);
+        }
+
+        // Cursor
+        if self.cursor.visible {
+            self.render_cursor(&transform, ctx);
+        }
+
+        Ok(())
+    }
+
+    /// The cursor is a one pixel sprite anchored on the centre of the image,
+    /// like the reference, so it moves and scales with the image.
+    fn render_cursor(&self, transform: &Transform, ctx: &mut MessageCtx) {
+        let image_size = self.node.sprite.size;
+        let Coords(pos) = self.cursor.coords;
 
+        let mut sprite = self.cursor.node.sprite;
+        sprite.anchor = Vector::new(
+            image_size.x as f32 / 2.0 - pos.x,
+            1.0 - image_size.y as f32 / 2.0 + pos.y,
+        );
+
+        let model = create_model_matrix(&sprite, transform);
+        self.cursor.texture.bind();
+        self.renderer.load_data(&[model], ctx.context);
+        self.renderer.render_instanced(ctx.context, 1);
+    }
+
+    /// Translation of each copy of the image to draw
+    fn tile_offsets(&self) -> Vec<Position> {
+        if !self.tile_preview {
+            return vec![Position::zeros()];
+        }
+
+        let size = self.node.sprite.size;
+        let scale = self.scale as f32;
+        let (width, height) = (size.x as f32 * scale, size.y as f32 * scale);
+
+        (-1..=1)
+            .flat_map(|y| (-1..=1).map(move |x| Position::new(x as f32 * width, y as f32 * height)))
+            .collect()
+    }
+
+    /// The background and the visible layers of the image
+    fn render_image(
+        &self,
+        background_texture: &Texture,
+        ctx: &mut MessageCtx,
+        image: &Image,
+        textures: &[Texture],
+        reference: Option<(&Reference, &Texture)>,
+        transform: &Transform,
+    ) {
         let mut sprite = self.node.sprite;
         sprite.z_index = 999;
-        let mut transform = self.node.transform;
-        // transform.scale_mut(self.scale);
-        let model = create_model_matrix(&sprite, &transform);
+        let model = create_model_matrix(&sprite, transform);
 
         // Render the "transparent" background texture
         background_texture.bind();
         self.renderer.load_data(&[model], ctx.context);
         self.renderer.render_instanced(ctx.context, 1);
 
-        // Render all layers
-        image.render(
-            &self.renderer,
-            sprite.clone(),
-            &transform,
-            &self.viewport,
-            ctx.context,
-        )?;
+        // Render all visible layers, bottom to top.
+        // Do NOT reverse these AGAIN!
+        // They are in the correct order now.
+        const SPRITE_Z: i32 = 150;
+        let layers = image.layers().iter().zip(textures).enumerate();
+        for (z_index, (_, texture)) in layers.filter(|(_, (layer, _))| layer.visible) {
+            sprite.z_index = SPRITE_Z - z_index as i32;
+            let model = create_model_matrix(&sprite, transform);
+            texture.bind();
+            self.renderer.load_data(&[model], ctx.context);
+            self.renderer.render_instanced(ctx.context, 1);
+        }
 
-        // Cursor
-        if self.cursor.visible {
-            self.renderer.render(
-                &self.cursor.texture,
-                &[self.cursor.node.relative_vertex_data(&transform)],
-                &self.viewport,
-                ctx.context,
-            )?;
+        // Reference, right below the bottom layer or right above the top layer
+        if let Some((reference, texture)) = reference {
+            let z_index = match reference.above {
+                true => SPRITE_Z - image.layer_count() as i32,
+                false => SPRITE_Z + 1,
+            };
+            self.render_reference(reference, texture, z_index, transform, ctx);
+        }
+
+        // Grid, above everything else
+        if let (true, Some(grid)) = (self.show_grid, &self.grid) {
+            let z_index = SPRITE_Z - image.layer_count() as i32 - 1;
+            self.render_lines(&grid.pixel_colour, &grid.pixel_lines, z_index, transform, ctx);
+            self.render_lines(&grid.tile_colour, &grid.tile_lines, z_index - 1, transform, ctx);
+        }
+    }
+
+    /// All the lines in one draw, placed like the reference
+    fn render_lines(
+        &self,
+        texture: &Texture,
+        lines: &[Line],
+        z_index: i32,
+        transform: &Transform,
+        ctx: &mut MessageCtx,
+    ) {
+        if lines.is_empty() {
+            return;
         }
 
-        // Container id
-        self.text_renderer.render(
-            &self.text.texture(),
-            &self.text.vertex_data(),
-            &self.viewport,
-            ctx.context,
+        let image_size = self.node.sprite.size;
+        let models = lines
+            .iter()
+            .map(|line| {
+                let mut sprite = Sprite::from_size(Size::new(line.width, line.height));
+                sprite.anchor = Vector::new(
+                    image_size.x as f32 / 2.0 - line.x,
+                    line.height - image_size.y as f32 / 2.0 + line.y,
+                );
+                sprite.z_index = z_index;
+                create_model_matrix(&sprite, transform)
+            })
+            .collect::<Vec<_>>();
+
+        texture.bind();
+        self.renderer.load_data(&models, ctx.context);
+        self.renderer.render_instanced(ctx.context, models.len());
+    }
+
+    /// The reference is anchored on the centre of the image, like the image itself,
+    /// so the anchor is where the centre of the image is on the reference.
+    fn render_reference(
+        &self,
+        reference: &Reference,
+        texture: &Texture,
+        z_index: i32,
+        transform: &Transform,
+        ctx: &mut MessageCtx,
+    ) {
+        let image_size = self.node.sprite.size;
+        let (offset_x, offset_y) = (reference.offset.0 as f32, reference.offset.1 as f32);
+        let width = reference.width() as f32 * reference.scale;
+        let height = reference.height() as f32 * reference.scale;
+
+        let mut sprite = Sprite::from_size(Size::new(width, height));
+        sprite.anchor = Vector::new(
+            image_size.x as f32 / 2.0 - offset_x,
+            height - image_size.y as f32 / 2.0 + offset_y,
         );
+        sprite.z_index = z_index;
 
-        Ok(())
+        let model = create_model_matrix(&sprite, transform);
+        texture.bind();
+        self.renderer.load_data(&[model], ctx.context);
+        self.renderer.render_instanced(ctx.context, 1);
     }
 
-    pub fn resize(&mut self) {
-        self.border.resize(&self.viewport);
+    /// Make a new grid if the image size changed, or the zoom changed the line width
+    /// or hid the pixel lines
+    pub fn update_grid(&mut self, image: &Image, ui: &Ui) {
+        let pixel_grid = self.scale >= ui.pixel_grid_min_scale;
+        let key = (image.width(), image.height(), grid_cell(self.scale), pixel_grid);
+        if !self.show_grid || key == self.grid_key {
+            return;
+        }
+
+        self.grid = grid(image.width(), image.height(), self.scale, ui);
+        self.grid_key = key;
     }
 
-    pub fn translate_mouse(&self, mouse_pos: Position, ctx: &MessageCtx) -> Position {
-        let transform = self.node.transform;
-        let canvas_pos = transform.translation;
-        let mut pos = Position::new(mouse_pos.x - canvas_pos.x, mouse_pos.y - canvas_pos.y);
+    /// Make the grid again on the next frame, after the config changed
+    pub fn reset_grid(&mut self) {
+        self.grid = None;
+        self.grid_key = (0, 0, 0, false);
+    }
 
-        pos.x /= self.scale.x;
-        pos.y /= self.scale.y;
+    /// Fit the border and the renderer to the viewport after the layout changed,
+    /// and centre the image again
+    pub fn resize(&mut self, ctx: &mut MessageCtx) -> Result<()> {
+        self.border.resize(&self.viewport);
+        self.renderer = SimpleRenderer::new(ctx.context, self.viewport.view_projection())?;
+        self.centre();
+        Ok(())
+    }
 
-        // let viewport_pos = ctx.canvas_viewport.position;
-        // let canvas_pos = self.node.transform.translation.cast::<i32>();
-        // let pos = mouse_pos - viewport_pos;
-        // let mut pos = pos - canvas_pos;
+    fn centre(&mut self) {
+        let pos = (*self.viewport.size() / 2).cast();
+        let mut transform = Transform::new(pos.to_vector());
+        transform.scale_mut(self.scale as f32);
+        self.node.transform = transform;
+    }
 
-        pos
+    /// The selected container has the active border
+    pub fn set_active(&mut self, active: bool) {
+        self.border.border_type = match active {
+            true => BorderType::Active,
+            false => BorderType::Inactive,
+        };
     }
 
+    /// The cursor shows the colour being drawn with
     pub fn set_colour(&mut self, colour: Pixel) {
-        self.colour = colour;
         self.cursor.set_colour(colour);
     }
 
-    pub fn set_alpha(&mut self, alpha: u8) {
-        self.colour.a = alpha;
-        self.set_colour(self.colour);
-    }
-
+    /// Zoom in or out, between 1 and `MAX_ZOOM` screen pixels per image pixel
     pub fn scale(&mut self, diff: i32) {
-        if diff > 0 && self.scale.x < MAX_ZOOM {
-            self.scale += Vector::new(diff, diff);
-        }
-        if diff < 0 && self.scale.x > 1 {
-            self.scale += Vector::new(diff, diff);
-        }
-
-        self.node.transform.scale_mut(self.scale);
+        self.scale = (self.scale as i32 + diff).max(1).min(MAX_ZOOM) as u32;
+        self.node.transform.scale_mut(self.scale as f32);
     }
 }
@@ -3,22 +3,18 @@
 //!
 //! All coordinates to be drawn should be of type `Coord` and not `Position<i32>`,
 //! to keep from translating positions multiple times.
-use std::path::Path;
-
-use anyhow::Result;
-use nightmare::events::{ButtonState, MouseButton};
+use anyhow::{bail, Result};
 use nightmare::pixels::Pixel;
 use nightmare::texture::Texture;
-use nightmare::{Position, Point, Size, Sprite, Transform, Viewport, Rect, Context, Vector};
+use nightmare::{Position, Size, Sprite, Viewport};
 
-use crate::Mouse;
-use crate::border::BorderType;
 use crate::canvas::LayerId;
+use crate::commandline::{Command, Direction, Target};
+use crate::document::{Document, Region};
 use crate::layout::{Split, Layout};
 use crate::listener::MessageCtx;
-use crate::message::Message;
 
-use super::{Container, Image, SaveBuffer, Coords};
+use super::{Container, Coords, RenderCache};
 
 
 // -----------------------------------------------------------------------------
@@ -30,15 +26,26 @@ pub struct Containers {
     pub(super) viewport: Viewport,
     /// Layout
     layout: Layout,
+    /// The layout from before the selected container was maximised
+    maximised: Option<Layout>,
     /// All containers
     inner: Vec<Container>,
     /// Selected container id
     selected: usize,
-    /// List of images to draw and render.
+    /// All images being edited.
     /// An image can be rendererd in multiple containers
     /// which is why the container has an `image_id` rather than owning
     /// an image.
-    images: Vec<Image>,
+    document: Document,
+    /// Layer textures for the images in the document
+    cache: RenderCache,
+    /// Pixels yanked by the last yank operator,
+    /// with their offset from the first one
+    yanked: Vec<(Coords, Pixel)>,
+    /// Where the last yank started
+    yank_start: Coords,
+    /// Where the cursor was when visual mode started
+    visual_anchor: Option<Coords>,
 }
 
 impl Containers {
@@ -48,216 +55,413 @@ impl Containers {
     pub fn new(viewport: Viewport, ctx: &mut MessageCtx) -> Result<Self> {
         let mut sprite = Sprite::from_size(Size::new(32, 32));
         sprite.anchor = (sprite.size / 2).to_vector();
-        let container = Container::new(
+        let mut container = Container::new(
             0,
             viewport.clone(),
             Split::Horz,
             ctx,
             sprite,
         )?;
+        container.set_active(true);
 
         let mut inst = Self {
             layout: Layout::Leaf { id: 0, size: *viewport.size(), pos: container.viewport.position },
+            maximised: None,
             selected: 0,
             inner: vec![container],
-            images: Vec::new(),
+            document: Document::new(),
+            cache: RenderCache::new(),
+            yanked: Vec::new(),
+            yank_start: Coords::zero(),
+            visual_anchor: None,
             viewport,
         };
 
-        let size = Size::new(32, 32);
-        let image = Image::new(size);
-        inst.add_image(size, image);
+        inst.apply(&Command::NewImage(Size::new(32.0, 32.0)))?;
 
         Ok(inst)
     }
 
-    /// Add a new image to the current container
+    /// Apply a command to the image in the selected container.
+    /// A new image is shown in the selected container,
+    /// and the cursor is kept inside an image that changed size.
+    pub fn apply(&mut self, command: &Command) -> Result<()> {
+        if let Some(image_id) = self.inner[self.selected].image_id {
+            self.document.select_image(image_id);
+        }
+
+        self.document.apply(command)?;
+
+        match command {
+            Command::NewImage(_)
+            | Command::Edit(_)
+            | Command::ShowImage(_)
+            | Command::Resize { .. }
+            | Command::Extend { .. }
+            | Command::Crop
+            | Command::Undo
+            | Command::Redo
+            | Command::Transform { .. } => {
+                if let Some(image_id) = self.document.current() {
+                    self.show_image(image_id);
+                }
+
+                let coords = self.selected().move_cursor_by(Coords::zero());
+                self.update_coords(self.clamp(coords));
+            }
+            Command::SetColour(_) | Command::SetAlpha(_) | Command::Pick(_) => {
+                let colour = self.document.colour;
+                self.selected().set_colour(colour);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Show an image from the document in the current container
     // TODO: can't delete an image now because of how stupid this is.
     //       Deleting an image would offset every image after it in
     //       the vector.
-    pub fn add_image(&mut self, size: Size, image: Image) {
-        let image_id = self.images.len();
-        self.images.push(image);
+    fn show_image(&mut self, image_id: usize) {
+        let image = match self.document.image_at(image_id) {
+            Some(image) => image,
+            None => return,
+        };
+        let size = Size::new(image.width() as f32, image.height() as f32);
 
         self.inner[self.selected].image_id = Some(image_id);
-        let mut selected = &mut self.inner[self.selected];
-        let sprite = Sprite::from_size(size);
+        let selected = &mut self.inner[self.selected];
+        let mut sprite = Sprite::from_size(size);
+        sprite.anchor = (sprite.size / 2).to_vector();
+        selected.node.sprite = sprite;
 
         // let pos = (*selected.viewport.size() / 2 / selected.renderer.pixel_size).to_vector();
         // selected.node.sprite = sprite;
         // selected.node.transform.translate_mut(pos);
     }
 
-    pub fn resize(&mut self, mut new_size: Size) {
-        // TODO: HACK! remove this dirt
-        // Do this because of the padding
-        new_size.width -= 128 * 2;
-        new_size.height -= 128 * 2;
-        // End of hack
-
-        // Rebuild the layout
+    /// `new_size` is the size of the canvas viewport,
+    /// which is the window size minus the padding.
+    pub fn resize(&mut self, new_size: Size, ctx: &mut MessageCtx) -> Result<()> {
+        self.viewport.resize(new_size);
         self.layout.set_size(new_size);
+        self.relayout(ctx)
+    }
+
+    /// Rebuild the layout and resize all containers after it
+    fn relayout(&mut self, ctx: &mut MessageCtx) -> Result<()> {
         self.layout.rebuild();
         self.layout.layout(&mut self.inner);
-
-        // Resize all containers after the new layout
-        self.inner.iter_mut().for_each(Container::resize);
+        self.inner.iter_mut().try_for_each(|container| container.resize(ctx))
     }
 
-    // This is horrid: 
-    // TODO: removing a split will ruin everything,
-    // because of the indexing.
-    //
-    // But rather than using an arena we can just rebuild it
+    /// Split the selected container in two, both showing the same image.
+    /// The new container is selected.
+    ///
+    /// The id of a container is its index in `inner`,
+    /// and the id of its leaf in the layout.
     pub fn split(&mut self, dir: Split, ctx: &mut MessageCtx) -> Result<()> {
+        let coords = self.selected().move_cursor_by(Coords::zero());
         let new_id = self.inner.len();
+        self.maximised = None;
         self.layout.split(self.selected, new_id, dir);
 
-        let selected = self.selected();
-
-        let selected_id = selected.container_id;
-        let viewport = selected.viewport.clone();
-        let sprite = selected.node.sprite.clone();
-
+        let selected = &self.inner[self.selected];
         let mut container = Container::new(
             new_id,
-            viewport,
-            Split::Horz,
+            selected.viewport.clone(),
+            dir,
             ctx,
-            sprite,
+            selected.node.sprite,
         )?;
 
         container.image_id = selected.image_id;
+        container.scale = selected.scale;
         self.inner.push(container);
 
-        self.layout.layout(&mut self.inner);
+        self.relayout(ctx)?;
+        self.select_container(new_id);
+        self.update_coords(coords);
+
+        Ok(())
+    }
+
+    /// Close the selected container and give its space to the container next to it,
+    /// which is then selected.
+    pub fn close_selected(&mut self, ctx: &mut MessageCtx) -> Result<()> {
+        if self.inner.len() == 1 {
+            bail!("can't close the last split");
+        }
+
+        let closed = self.selected;
+        self.maximised = None;
+        let next = [Direction::Left, Direction::Up, Direction::Right, Direction::Down]
+            .iter()
+            .find_map(|dir| self.layout.neighbour(closed, *dir))
+            .unwrap_or_default();
+
+        self.layout.collapse(closed);
+        self.layout.remove_id(closed);
+        self.inner.remove(closed);
         self.inner
             .iter_mut()
-            .filter(|cont| cont.container_id == new_id || cont.container_id == selected_id)
-            .for_each(|cont| {
-                cont.border.resize(&cont.viewport);
-                let mut cur_pos = cont.node.transform.translation;
-                match dir {
-                    Split::Horz => cur_pos.y /= 2,
-                    Split::Vert => cur_pos.x /= 2,
-                };
-                cont.node.transform.translate_mut(cur_pos);
-            });
-
-        // Set the active border.
-        // Ignore the previous active border as that
-        // is not rendered since it now has children.
-        let mut selected = &mut self.inner[self.selected];
-        selected.border.border_type = BorderType::Active;
+            .enumerate()
+            .for_each(|(id, container)| container.container_id = id);
+
+        self.relayout(ctx)?;
+
+        let next = match next > closed {
+            true => next - 1,
+            false => next,
+        };
+        self.select_container(next);
 
         Ok(())
     }
 
-    // TODO: removing the last container should close the application.
-    pub fn close_selected(&mut self) {
-        // TODO: put this back in once we can merge nodes in the bin tree.
-        // let selected = self.inner.sibling(self.selected);
-        // self.inner.remove(self.selected);
-        // if let Some(selected) = selected {
-        //     self.inner.collapse_into_parent(selected);
-        //     self.selected = selected;
-        // }
+    /// Grow the selected container, taking the space from the containers next to it,
+    /// or shrink it with negative numbers
+    pub fn grow_selected(&mut self, width: i32, height: i32, ctx: &mut MessageCtx) -> Result<()> {
+        self.layout.grow(self.selected, width, height);
+        self.relayout(ctx)
+    }
+
+    pub fn equalise(&mut self, ctx: &mut MessageCtx) -> Result<()> {
+        self.maximised = None;
+        self.layout.equalise();
+        self.relayout(ctx)
+    }
+
+    /// Give the selected container the whole canvas, leaving the smallest possible
+    /// space to the others, or put the layout back the way it was before.
+    pub fn toggle_maximise(&mut self, ctx: &mut MessageCtx) -> Result<()> {
+        match self.maximised.take() {
+            Some(mut layout) => {
+                // The canvas could have been resized since
+                layout.set_size(*self.viewport.size());
+                self.layout = layout;
+            }
+            None => {
+                self.maximised = Some(self.layout.clone());
+                self.layout.resize(self.selected, *self.viewport.size());
+            }
+        }
+
+        self.relayout(ctx)
+    }
+
+    /// Select the container next to the selected one.
+    /// Returns false if there is no container in that direction.
+    pub fn focus(&mut self, direction: Direction) -> bool {
+        match self.layout.neighbour(self.selected, direction) {
+            Some(id) => {
+                self.select_container(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn select_container(&mut self, id: usize) {
+        self.selected = id;
+        self.inner
+            .iter_mut()
+            .enumerate()
+            .for_each(|(index, container)| container.set_active(index == id));
     }
 
     pub fn render(&mut self, background: &Texture, ctx: &mut MessageCtx) -> Result<()> {
-        for (id, container) in self.inner.iter_mut().enumerate() {
-            let image = match container.image_id {
-                Some(id) => &mut self.images[id],
+        self.cache.update(&mut self.document);
+
+        for container in &mut self.inner {
+            let (image, textures) = match container.image_id {
+                Some(id) => match self.document.image_at(id) {
+                    Some(image) => (image, self.cache.textures(id)),
+                    None => continue,
+                },
                 None => continue,
             };
 
-            if image.dirty {
-                image.redraw_layers();
-            }
+            let reference = match (&self.document.reference, self.cache.reference()) {
+                (Some(reference), Some(texture)) => Some((reference, texture)),
+                _ => None,
+            };
 
-            container.render(background, ctx, image)?;
+            container.update_grid(image, &ctx.config.ui);
+            container.render(background, ctx, image, textures, reference)?;
         }
 
         Ok(())
     }
 
+    /// Everything drawn until `end_stroke` is undone as one
+    pub fn begin_stroke(&mut self) {
+        if let Some(image_id) = self.inner[self.selected].image_id {
+            self.document.select_image(image_id);
+        }
+
+        self.document.begin_stroke();
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.document.end_stroke();
+    }
+
+    /// Draw with the selected colour.
+    /// Drawing outside of the image, or without an image, does nothing.
     pub fn draw(&mut self, coords: Coords) {
-        let container = &mut self.inner[self.selected];
-        let image = match container.image_id {
-            Some(id) => &mut self.images[id],
-            None => return,
+        let coords = self.wrap(coords);
+        let _ = self.apply(&Command::Put(Target::Pixel(coords.0)));
+    }
+
+    pub fn clear_pixel(&mut self, coords: Coords) {
+        let coords = self.wrap(coords);
+        let _ = self.apply(&Command::Clear(Target::Pixel(coords.0)));
+    }
+
+    /// Yank the pixel at `coords` into the yank register.
+    /// Starting a new yank replaces the previous register.
+    pub fn yank(&mut self, coords: Coords, new_yank: bool) {
+        if new_yank {
+            self.yanked.clear();
+            self.yank_start = coords;
+        }
+
+        let offset = Coords::new(coords.0.x - self.yank_start.0.x, coords.0.y - self.yank_start.0.y);
+        let coords = self.wrap(coords);
+        let image = match self.inner[self.selected].image_id {
+            Some(id) => self.document.image_at(id),
+            None => None,
         };
 
-        image.put_pixel(container.colour, coords);
+        if let Some(pixel) = image.and_then(|image| image.layer().pixel(coords.0.x as i32, coords.0.y as i32)) {
+            self.yanked.push((offset, pixel));
+        }
     }
 
-    pub fn clear_pixel(&mut self, coords: Coords) {
-        let container = &mut self.inner[self.selected];
+    /// Paste the yank register with its first pixel at `coords`.
+    /// The pixels are pasted with their own colour, and undone as one.
+    pub fn paste(&mut self, coords: Coords) -> Result<()> {
+        if self.yanked.is_empty() {
+            bail!("nothing to paste, yank some pixels first");
+        }
 
-        let image = match container.image_id {
-            Some(id) => &mut self.images[id],
-            None => return,
+        let mut commands = Vec::with_capacity(self.yanked.len() * 2 + 1);
+        for (offset, pixel) in &self.yanked {
+            let pos = self.wrap(coords + *offset).0;
+            commands.push(Command::SetColour(*pixel));
+            commands.push(Command::Put(Target::Pixel(pos)));
+        }
+        commands.push(Command::SetColour(self.document.colour));
+
+        self.apply(&Command::Batch(commands))
+    }
+
+    /// Keep the coords inside the selected image
+    pub fn clamp(&self, coords: Coords) -> Coords {
+        let (width, height) = match self.selected_image() {
+            Some(image) => (image.width() as f32, image.height() as f32),
+            None => return coords,
         };
 
-        image.clear_pixel(coords);
+        Coords::new(
+            coords.0.x.max(0.0).min(width - 1.0),
+            coords.0.y.max(0.0).min(height - 1.0),
+        )
+    }
+
+    /// Set the colour from the pixel under the cursor
+    pub fn pick(&mut self) -> Result<()> {
+        let coords = self.selected().move_cursor_by(Coords::zero());
+        let coords = self.wrap(coords);
+        self.apply(&Command::Pick(coords.0))
+    }
+
+    /// Coords on a copy of the image in the tile preview
+    /// are moved to the same pixel on the image
+    fn wrap(&self, coords: Coords) -> Coords {
+        if !self.inner[self.selected].tile_preview {
+            return coords;
+        }
+
+        match self.selected_image() {
+            Some(image) => coords.wrap(image.width() as f32, image.height() as f32),
+            None => coords,
+        }
+    }
+
+    pub fn toggle_tile_preview(&mut self) {
+        let selected = self.selected();
+        selected.tile_preview = !selected.tile_preview;
+    }
+
+    pub fn toggle_grid(&mut self) {
+        let selected = self.selected();
+        selected.show_grid = !selected.show_grid;
+    }
+
+    pub fn reset_grids(&mut self) {
+        self.inner.iter_mut().for_each(Container::reset_grid);
+    }
+
+    /// Select from the cursor, the selection follows the cursor until it's cleared
+    pub fn start_selection(&mut self) {
+        let coords = self.selected().move_cursor_by(Coords::zero());
+        self.visual_anchor = Some(coords);
+        self.select(coords, coords);
+    }
+
+    /// Select the pixels between two corners, both included
+    fn select(&mut self, from: Coords, to: Coords) {
+        let (from, to) = (self.clamp(from), self.clamp(to));
+        let (x, y) = (from.0.x.min(to.0.x) as usize, from.0.y.min(to.0.y) as usize);
+        let width = (from.0.x - to.0.x).abs() as usize + 1;
+        let height = (from.0.y - to.0.y).abs() as usize + 1;
+
+        self.document.selection = Some(Region::new(x, y, width, height));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.visual_anchor = None;
+        self.document.selection = None;
     }
 
     pub fn update_coords(&mut self, coords: Coords) {
+        if let Some(anchor) = self.visual_anchor {
+            self.select(anchor, coords);
+        }
+
         // Update the cursor position for all 
         // containers that is currently displaying the 
         // selected image.
         let image_id = self.selected().image_id;
-        let selected = self.selected;
         self.inner
             .iter_mut()
             .filter(|cont| cont.image_id == image_id) 
             .for_each(|cont| cont.move_cursor(coords));
     }
 
-    pub fn set_colour(&mut self, colour: Pixel) {
-        let container = self.selected();
-        container.set_colour(colour);
-    }
-
-    pub fn set_alpha(&mut self, alpha: u8) {
-        let container = self.selected();
-        container.set_alpha(alpha);
-    }
-
     pub(super) fn selected(&mut self) -> &mut Container {
         &mut self.inner[self.selected]
     }
 
-    pub(super) fn selected_image(&mut self) -> Option<&mut Image> {
-        let id = self.inner[self.selected].image_id?;
-        self.images.get_mut(id)
+    pub(super) fn document_mut(&mut self) -> &mut Document {
+        &mut self.document
     }
 
-    pub(super) fn new_layer(&mut self) -> Option<(LayerId, usize)> {
-        let size = self.selected().node.sprite.size;
-        self.selected_image().map(|image| image.new_layer(size.cast()))
+    pub(super) fn layer_count(&self) -> usize {
+        self.selected_image().map(|image| image.layer_count()).unwrap_or(0)
     }
 
-    pub(super) fn set_layer(&mut self, mut layer_id: LayerId) -> Option<(LayerId, usize)> {
-        if layer_id.as_display() == 0 {
-            return None;
-        }
-
-        self.selected_image().map(|image| image.set_layer(layer_id))
-    }
-
-    pub(super) fn remove_layer(&mut self) -> Option<(LayerId, usize)> {
-        self.selected_image().and_then(Image::remove_layer)
+    /// The current layer and the number of layers in the selected image
+    pub(super) fn current_layer(&self) -> Option<(LayerId, usize)> {
+        self.selected_image().map(|image| (LayerId::from_display(image.current_layer()), image.layer_count()))
     }
 
-    pub(super) fn save_current(&mut self, path: impl AsRef<Path>, overwrite: bool, context: &mut Context) {
-        if !overwrite && path.as_ref().exists() {
-            return
-        }
-        let size = self.selected().node.sprite.size.cast::<i32>();
-        let image = self.selected_image().unwrap();
-        let mut save_buf = SaveBuffer::new(context, size).unwrap();
-        save_buf.save(path, image, size, context);
+    fn selected_image(&self) -> Option<&crate::document::Image> {
+        let id = self.inner[self.selected].image_id?;
+        self.document.image_at(id)
     }
 
     pub(super) fn change_scale(&mut self, diff: i32) {
@@ -1,5 +0,0 @@
-use nightmare::Size;
-
-pub enum Canvas {
-    NewCanvas(Size)
-}
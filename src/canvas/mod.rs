@@ -2,33 +2,35 @@
 //! as containers does not implement `Listener`.
 //!
 //! So in essence this just routes messages to the `Containers`.
-use anyhow::Result;
-use nightmare::{ VertexData, Viewport, Transform, Position };
+use anyhow::{Context as _, Result};
+use log::error;
+use nightmare::{Position, Viewport};
 use nightmare::texture::Texture;
 use nightmare::events::{ButtonState, MouseButton};
+use nightmare::pixels::{Pixel, Pixels};
+use nightmare::Size;
 
-use crate::commandline::Command;
-use crate::input::Input;
+use crate::application::Mode;
+use crate::commandline::{Candidates, Command, Direction, Target};
+use crate::input::{Input, Operator};
+use crate::layout::Split;
 use crate::listener::{MessageCtx, Listener};
 use crate::plugins::Plugin;
 use crate::{Coords, Message};
 
-pub mod message;
 mod containers;
 mod layer;
-mod image;
 mod cursor;
 mod container;
-mod savebuffer;
+mod render;
 
-use crate::config::Action;
+use crate::config::{Action, Ui};
 
 pub use container::Container;
 pub use containers::Containers;
 pub use cursor::Cursor;
-pub use image::Image;
 pub use layer::LayerId;
-pub use savebuffer::SaveBuffer;
+pub use render::RenderCache;
 
 pub struct Canvas {
     /// All <whatevers> 
@@ -39,26 +41,20 @@ pub struct Canvas {
     plugin: Plugin,
 
     drag_pos: Option<Position>,
+    /// Drawing with the mouse button held, undone as one stroke
+    drawing: bool,
 }
 
 impl Canvas {
     pub fn new(viewport: Viewport, ctx: &mut MessageCtx) -> Result<Self> {
-        // TODO: pfft, lazy! 
-        //       Don't panic, return the error good and proper.
-        // let plugin = match Plugin::new().map_err(|p| {}) {
-        let plugin = match Plugin::new() {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("{:?}", e);
-                panic!();
-            }
-        };
+        let plugin = Plugin::new().context("failed to load the plugins")?;
 
         let inst = Self {
-            background: Texture::from_disk("background.png")?,
+            background: checkerboard(&ctx.config.ui),
             containers: Containers::new(viewport, ctx)?,
             plugin,
             drag_pos: None,
+            drawing: false,
         };
 
         Ok(inst)
@@ -66,78 +62,302 @@ impl Canvas {
 
     fn change_cursor_coords(&mut self, coords: Coords) -> Message {
         let coords = self.containers.selected().move_cursor_by(coords);
-        eprintln!("{:?}", coords);
+        self.containers.update_coords(coords);
+        Message::CursorCoords(coords)
+    }
+
+    /// The layer of the image in the selected container,
+    /// for when another container or image is selected
+    fn layer_changed(&self) -> Message {
+        match self.containers.current_layer() {
+            Some((layer, total_layers)) => Message::LayerChanged { layer, total_layers },
+            None => Message::Noop,
+        }
+    }
+
+    fn split(&mut self, dir: Split, ctx: &mut MessageCtx) -> Message {
+        match self.containers.split(dir, ctx) {
+            Ok(()) => self.layer_changed(),
+            Err(e) => Message::CommandError(e.to_string()),
+        }
+    }
+
+    fn focus(&mut self, direction: Direction) -> Message {
+        match self.containers.focus(direction) {
+            true => self.layer_changed(),
+            false => Message::Noop,
+        }
+    }
+
+    /// Apply the operator to the pixel under the cursor,
+    /// and every pixel the motion passes over, `count` times.
+    fn apply_operator(&mut self, operator: Operator, motion: Option<Action>, count: usize) -> Message {
+        let step = motion.as_ref().and_then(motion_offset);
+        let mut coords = self.containers.selected().move_cursor_by(Coords::zero());
+
+        // A doubled operator (`3dd`) covers `count` pixels to the right
+        // and leaves the cursor where it was
+        let (step, steps) = match step {
+            Some(step) => (step, count),
+            None => (Coords::new(1.0, 0.0), count.saturating_sub(1)),
+        };
+        let start = coords;
+
+        self.containers.begin_stroke();
+        for i in 0..=steps {
+            if i > 0 {
+                coords += step;
+            }
+
+            match operator {
+                Operator::Erase => self.containers.clear_pixel(coords),
+                Operator::Paint => self.containers.draw(coords),
+                Operator::Yank => self.containers.yank(coords, i == 0),
+            }
+        }
+        self.containers.end_stroke();
+
+        if motion.is_none() {
+            coords = start;
+        }
+
         self.containers.update_coords(coords);
         Message::CursorCoords(coords)
     }
 }
 
+/// Lines per image pixel the grid is drawn for.
+/// Lines are a `1 / cell` of an image pixel wide, so this keeps them thin
+/// at high zoom without going below one screen pixel when zoomed out.
+const MAX_GRID_CELL: u32 = 8;
+
+pub(super) fn grid_cell(scale: u32) -> u32 {
+    scale.max(1).min(MAX_GRID_CELL)
+}
+
+// -----------------------------------------------------------------------------
+//     - Grid -
+//     Lines between the pixels of an image and around its tiles,
+//     drawn on top of the image.
+// -----------------------------------------------------------------------------
+pub(super) struct Grid {
+    /// A single texel of each line colour, stretched over the lines
+    pub pixel_colour: Texture,
+    pub tile_colour: Texture,
+    pub pixel_lines: Vec<Line>,
+    pub tile_lines: Vec<Line>,
+}
+
+/// Top left corner and size of a line, in image pixels
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Line {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// The grid lines for an image. Pixel lines are left out below `pixel_grid_min_scale`.
+pub(super) fn grid(width: usize, height: usize, scale: u32, ui: &Ui) -> Option<Grid> {
+    let pixel_grid = scale >= ui.pixel_grid_min_scale;
+    let [tile_width, tile_height] = ui.tile_grid;
+    let tile_grid = tile_width > 0 && tile_height > 0;
+
+    if !pixel_grid && !tile_grid {
+        return None;
+    }
+
+    let thickness = 1.0 / grid_cell(scale) as f32;
+    let lines = |step_x: usize, step_y: usize| {
+        let columns = (0..width).step_by(step_x).map(|x| Line {
+            x: x as f32,
+            y: 0.0,
+            width: thickness,
+            height: height as f32,
+        });
+        let rows = (0..height).step_by(step_y).map(|y| Line {
+            x: 0.0,
+            y: y as f32,
+            width: width as f32,
+            height: thickness,
+        });
+        columns.chain(rows).collect::<Vec<_>>()
+    };
+
+    let grid = Grid {
+        pixel_colour: colour_texture(ui.pixel_grid_colour.into()),
+        tile_colour: colour_texture(ui.tile_grid_colour.into()),
+        pixel_lines: if pixel_grid { lines(1, 1) } else { Vec::new() },
+        tile_lines: if tile_grid { lines(tile_width, tile_height) } else { Vec::new() },
+    };
+
+    Some(grid)
+}
+
+fn colour_texture(colour: Pixel) -> Texture {
+    let size = Size::new(1, 1);
+    Texture::default_with_data(size.cast(), Pixels::from_pixel(colour, size).as_bytes())
+}
+
+/// Background shown through transparent pixels
+fn checkerboard(ui: &Ui) -> Texture {
+    let squares = ui.checkerboard_squares.max(1);
+    let size = Size::new(squares, squares);
+    let [light, dark] = ui.checkerboard;
+
+    let mut pixels = Pixels::from_pixel(Pixel::transparent(), size);
+    pixels.iter_mut().enumerate().for_each(|(i, pixel)| {
+        let (x, y) = (i % squares, i / squares);
+        *pixel = match (x + y) % 2 {
+            0 => light.into(),
+            _ => dark.into(),
+        };
+    });
+
+    Texture::default_with_data(size.cast(), pixels.as_bytes())
+}
+
+fn motion_offset(action: &Action) -> Option<Coords> {
+    use Action::*;
+    let offset = match action {
+        Left => Coords::new(-1.0, 0.0),
+        Right => Coords::new(1.0, 0.0),
+        Up => Coords::new(0.0, -1.0),
+        Down => Coords::new(0.0, 1.0),
+        UpLeft => Coords::new(-1.0, -1.0),
+        UpRight => Coords::new(1.0, -1.0),
+        DownLeft => Coords::new(-1.0, 1.0),
+        DownRight => Coords::new(1.0, 1.0),
+        _ => return None,
+    };
+
+    Some(offset)
+}
+
 impl Listener for Canvas {
     fn message(&mut self, message: &Message, ctx: &mut MessageCtx) -> Message {
         match message {
-            Message::Resize(new_size) => {
-                self.containers.resize(new_size.cast());
-            }
-            Message::Command(Command::Split(dir)) => {
-                self.containers.split(*dir, ctx);
-            }
-            Message::Command(Command::NewImage(size)) => {
-                let image = Image::new(*size);
-                self.containers.add_image(size.cast(), image);
+            Message::Resize(_) => {
+                if let Err(e) = self.containers.resize(*ctx.canvas_viewport.size(), ctx) {
+                    error!("Failed to resize the canvas: {:?}", e);
+                }
             }
+            Message::Command(Command::Split(dir)) => return self.split(*dir, ctx),
             Message::Command(Command::CloseSelectedSplit) => {
-                self.containers.close_selected();
-            }
-            Message::Command(Command::Put(pos)) => {
-                let coords = Coords(*pos);
-                self.containers.draw(coords);
+                return match self.containers.close_selected(ctx) {
+                    Ok(()) => self.layer_changed(),
+                    Err(e) => Message::CommandError(e.to_string()),
+                };
             }
-            Message::Command(Command::Clear(pos)) => {
-                let coords = Coords(*pos);
-                self.containers.clear_pixel(coords);
-            }
-            Message::Command(Command::SetColour(colour)) => {
-                self.containers.set_colour(*colour);
+            Message::Command(Command::GrowSplit { width, height }) => {
+                if let Err(e) = self.containers.grow_selected(*width, *height, ctx) {
+                    return Message::CommandError(e.to_string());
+                }
             }
-            Message::Command(Command::SetAlpha(alpha)) => {
-                self.containers.set_alpha(*alpha);
+            Message::Command(Command::EqualiseSplits) => {
+                if let Err(e) = self.containers.equalise(ctx) {
+                    return Message::CommandError(e.to_string());
+                }
             }
-            Message::Command(Command::NewLayer) => {
-                match self.containers.new_layer() {
-                    Some((layer, total_layers)) => return Message::LayerChanged { layer, total_layers },
-                    None => {}
+            Message::Command(Command::MaximiseSplit) => {
+                if let Err(e) = self.containers.toggle_maximise(ctx) {
+                    return Message::CommandError(e.to_string());
                 }
             }
-            Message::Command(Command::RemoveLayer) => {
-                match self.containers.remove_layer() {
-                    Some((layer, total_layers)) => return Message::LayerChanged { layer, total_layers },
-                    None => {}
+            Message::Command(command @ Command::NewLayer)
+            | Message::Command(command @ Command::RemoveLayer)
+            | Message::Command(command @ Command::ChangeLayer(_))
+            | Message::Command(command @ Command::NewImage(_))
+            | Message::Command(command @ Command::Edit(_))
+            | Message::Command(command @ Command::ShowImage(_)) => {
+                if let Err(e) = self.containers.apply(command) {
+                    return Message::CommandError(e.to_string());
                 }
+
+                return self.layer_changed();
             }
-            Message::Command(Command::ChangeLayer(layer)) => {
-                match self.containers.set_layer(*layer) {
-                    Some((layer, total_layers)) => return Message::LayerChanged { layer, total_layers },
-                    None => {}
+            Message::Command(command @ Command::Put(_))
+            | Message::Command(command @ Command::Clear(_))
+            | Message::Command(command @ Command::SetColour(_))
+            | Message::Command(command @ Command::SetAlpha(_))
+            | Message::Command(command @ Command::LayerVisibility { .. })
+            | Message::Command(command @ Command::Save { .. })
+            | Message::Command(command @ Command::Resize { .. })
+            | Message::Command(command @ Command::Extend { .. })
+            | Message::Command(command @ Command::Crop)
+            | Message::Command(command @ Command::Undo)
+            | Message::Command(command @ Command::Redo)
+            | Message::Command(command @ Command::Transform { .. })
+            | Message::Command(command @ Command::Pick(_))
+            | Message::Command(command @ Command::Reference(_)) => {
+                if let Err(e) = self.containers.apply(command) {
+                    return Message::CommandError(e.to_string());
                 }
             }
-            Message::Command(Command::Save { path, overwrite }) => {
-                self.containers.save_current(path, *overwrite, ctx.context);
+            Message::ModeChanged(Mode::Command) => {
+                let candidates = Candidates {
+                    layers: (1..=self.containers.layer_count()).map(|n| n.to_string()).collect(),
+                    lua_globals: self.plugin.globals(),
+                    ..Default::default()
+                };
+                return Message::Candidates(candidates);
+            }
+            Message::ModeChanged(Mode::Visual) => self.containers.start_selection(),
+            Message::ModeChanged(Mode::Normal) | Message::ModeChanged(Mode::Insert) => {
+                self.containers.clear_selection();
             }
             Message::ReloadPlugin(path) => {
-                self.plugin.reload(path);
+                if let Err(e) = self.plugin.reload(path) {
+                    return Message::CommandError(format!("lua: {}", e));
+                }
+            }
+            Message::ConfigReloaded => {
+                self.background = checkerboard(&ctx.config.ui);
+                self.containers.reset_grids();
+                if let Err(e) = self.containers.resize(*ctx.canvas_viewport.size(), ctx) {
+                    error!("Failed to resize the canvas: {:?}", e);
+                }
             }
             Message::Command(Command::Lua(code)) => {
-                self.plugin.exec_code(code, &mut self.containers);
+                if let Err(e) = self.plugin.exec_code(code, self.containers.document_mut()) {
+                    return Message::CommandError(format!("lua: {}", e));
+                }
+            }
+            Message::Operator { operator, motion, count } => {
+                return self.apply_operator(*operator, motion.clone(), *count);
             }
             Message::Action(action) => {
                 use Action::*;
                 match action {
-                    Left => return self.change_cursor_coords(Coords::new(-1, 0)),
-                    Right => return self.change_cursor_coords(Coords::new(1, 0)),
-                    Up => return self.change_cursor_coords(Coords::new(0, -1)),
-                    Down => return self.change_cursor_coords(Coords::new(0, 1)),
-                    CanvasZoomIn => self.containers.selected().scale += 1,
-                    CanvasZoomOut => self.containers.selected().scale -= 1,
+                    Left | Right | Up | Down | UpLeft | UpRight | DownLeft | DownRight => {
+                        if let Some(offset) = motion_offset(action) {
+                            return self.change_cursor_coords(offset);
+                        }
+                    }
+                    Fill => {
+                        let _ = self.containers.apply(&Command::Put(Target::Layer));
+                    }
+                    SplitViewportHorz => return self.split(Split::Horz, ctx),
+                    SplitViewportVert => return self.split(Split::Vert, ctx),
+                    FocusLeft => return self.focus(Direction::Left),
+                    FocusRight => return self.focus(Direction::Right),
+                    FocusUp => return self.focus(Direction::Up),
+                    FocusDown => return self.focus(Direction::Down),
+                    ToggleTilePreview => self.containers.toggle_tile_preview(),
+                    ToggleGrid => self.containers.toggle_grid(),
+                    PickColour => {
+                        if let Err(e) = self.containers.pick() {
+                            return Message::CommandError(e.to_string());
+                        }
+                    }
+                    Paste => {
+                        let coords = self.containers.selected().move_cursor_by(Coords::zero());
+                        if let Err(e) = self.containers.paste(coords) {
+                            return Message::CommandError(e.to_string());
+                        }
+                    }
+                    CanvasZoomIn => self.containers.change_scale(1),
+                    CanvasZoomOut => self.containers.change_scale(-1),
                     _ => {}
                 }
             }
@@ -152,9 +372,9 @@ impl Listener for Canvas {
                 let padding = self.containers.viewport.position;
                 let mut offset_pos = mouse.pos.cast::<f32>() - node_pos.cast::<f32>() - padding.cast::<f32>();
 
-                let scale = self.containers.selected().scale.cast::<f32>();
-                offset_pos.x /= scale.x;
-                offset_pos.y /= scale.y;
+                let scale = self.containers.selected().scale as f32;
+                offset_pos.x /= scale;
+                offset_pos.y /= scale;
                 offset_pos += anchor;
                 offset_pos.y = node.sprite.size.height as f32 - offset_pos.y;
 
@@ -163,6 +383,12 @@ impl Listener for Canvas {
                 self.containers.update_coords(coords);
                 match mouse.state {
                     ButtonState::Pressed => {
+                        let draws = matches!(mouse.button, Some(MouseButton::Left) | Some(MouseButton::Right));
+                        if draws && !self.drawing {
+                            self.containers.begin_stroke();
+                            self.drawing = true;
+                        }
+
                         if let Some(MouseButton::Left) = mouse.button {
                             self.containers.draw(coords);
                         }
@@ -188,6 +414,11 @@ impl Listener for Canvas {
                         if let Some(MouseButton::Middle) = mouse.button {
                             self.drag_pos = None;
                         }
+
+                        if self.drawing {
+                            self.containers.end_stroke();
+                            self.drawing = false;
+                        }
                     }
                 }
 
@@ -202,15 +433,22 @@ impl Listener for Canvas {
             | Message::ModeChanged(_)
             | Message::Command(_)
             | Message::CursorCoords(_)
+            | Message::PendingKeys(_)
+            | Message::Candidates(_)
+            | Message::CommandError(_)
+            | Message::RepeatAction(..)
             | Message::LayerChanged { .. }
+            | Message::Tick
             | Message::Noop => {}
         }
 
         Message::Noop
     }
 
-    fn render(&mut self, ctx: &mut MessageCtx) -> Result<()> {
-        self.containers.render(&self.background, ctx)
+    fn render(&mut self, ctx: &mut MessageCtx) {
+        if let Err(e) = self.containers.render(&self.background, ctx) {
+            error!("Failed to render the canvas: {:?}", e);
+        }
     }
 
 }
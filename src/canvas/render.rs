@@ -0,0 +1,108 @@
+//! Textures for the layers in the document.
+//!
+//! The document knows nothing about the GPU, so the cache
+//! keeps one texture per layer and uploads the part of each layer
+//! that changed since the last frame.
+use nightmare::{Position, Size};
+use nightmare::texture::Texture;
+
+use crate::document::{Document, Image, Region};
+
+// -----------------------------------------------------------------------------
+//     - Image textures -
+// -----------------------------------------------------------------------------
+struct ImageTextures {
+    width: usize,
+    height: usize,
+    layers: Vec<Texture>,
+}
+
+impl ImageTextures {
+    fn new(image: &Image) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            layers: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, image: &mut Image) {
+        // The layers are recreated if the image changed size
+        if (self.width, self.height) != (image.width(), image.height()) {
+            *self = Self::new(image);
+        }
+
+        let size = Size::new(self.width as f32, self.height as f32);
+        self.layers.truncate(image.layer_count());
+
+        for (index, layer) in image.layers_mut().iter_mut().enumerate() {
+            let dirty = layer.take_dirty();
+
+            // New layers are uploaded whole
+            if index == self.layers.len() {
+                let bytes = layer.bytes(Region::new(0, 0, self.width, self.height));
+                self.layers.push(Texture::default_with_data(size.cast(), &bytes));
+                continue;
+            }
+
+            if let Some(region) = dirty {
+                let position = Position::new(region.x as f32, region.y as f32);
+                let region_size = Size::new(region.width as f32, region.height as f32);
+                self.layers[index].write_region(position.cast(), region_size.cast(), &layer.bytes(region));
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Render cache -
+// -----------------------------------------------------------------------------
+pub struct RenderCache {
+    /// One entry per image in the document, in the same order
+    images: Vec<ImageTextures>,
+    reference: Option<Texture>,
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        Self { images: Vec::new(), reference: None }
+    }
+
+    /// Upload every dirty layer of every image.
+    /// Call this before rendering, once per frame.
+    pub fn update(&mut self, document: &mut Document) {
+        let images = document.images_mut();
+        self.images.truncate(images.len());
+
+        for (index, image) in images.iter_mut().enumerate() {
+            if index == self.images.len() {
+                self.images.push(ImageTextures::new(image));
+            }
+
+            self.images[index].update(image);
+        }
+
+        // The opacity is part of the texture, so any change uploads the whole reference
+        match document.reference.as_mut() {
+            Some(reference) => {
+                if reference.take_dirty() || self.reference.is_none() {
+                    let size = Size::new(reference.width() as f32, reference.height() as f32);
+                    self.reference = Some(Texture::default_with_data(size.cast(), &reference.bytes()));
+                }
+            }
+            None => self.reference = None,
+        }
+    }
+
+    pub fn reference(&self) -> Option<&Texture> {
+        self.reference.as_ref()
+    }
+
+    /// Layer textures for an image, bottom to top
+    pub fn textures(&self, image_id: usize) -> &[Texture] {
+        match self.images.get(image_id) {
+            Some(image) => &image.layers,
+            None => &[],
+        }
+    }
+}
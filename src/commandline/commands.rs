@@ -1,8 +1,10 @@
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
 use nightmare::{Position, Size};
 use nightmare::pixels::Pixel;
 
-// use crate::layout::Split;
-// use crate::canvas::LayerId;
+use crate::layout::Split;
 // use crate::plugins::PluginCall;
 
 #[derive(Debug)]
@@ -10,16 +12,182 @@ pub enum Command {
     Noop,
     Quit,
     Save { path: String, overwrite: bool },
-    Put(Position),
+    Put(Target),
     SetColour(Pixel),
     SetAlpha(u8),
-    Clear(Position),
+    Clear(Target),
     NewImage(Size),
-    // Split(Split),
+    /// Open a png as a new image
+    Edit(String),
+    /// Image number as displayed, starting at one
+    ShowImage(usize),
+    Split(Split),
     CloseSelectedSplit,
+    /// Grow the selected split by a number of pixels, or shrink it with negative numbers
+    GrowSplit { width: i32, height: i32 },
+    /// Give every split the same share of the canvas
+    EqualiseSplits,
+    /// Give the selected split the whole canvas, or restore the splits if it already has it
+    MaximiseSplit,
     NewLayer,
     RemoveLayer,
-    // ChangeLayer(LayerId),
+    /// Layer number as displayed, starting at one
+    ChangeLayer(usize),
+    /// Show or hide a range of layers, or the current layer if there is no range
+    LayerVisibility { layers: Option<RangeInclusive<usize>>, visible: bool },
     Lua(String),
     Log(String),
+    /// `:command Name commands`: define an alias at runtime
+    Define { name: String, body: String },
+    /// Commands from an alias, run in order
+    Batch(Vec<Command>),
+    /// Run the commands in a script file
+    Source(PathBuf),
+    /// Resize the image, keeping the pixels at the anchor in place
+    Resize { width: usize, height: usize, anchor: Anchor },
+    /// Grow the image on one side, or shrink it with a negative amount
+    Extend { direction: Direction, amount: i32 },
+    /// Crop to the selection, or to the pixels that aren't transparent
+    Crop,
+    Undo,
+    Redo,
+    /// Flip, rotate or shift the pixels in the selection, or the whole layer.
+    /// Applies to every layer with `all_layers`, otherwise to the current layer.
+    Transform { transform: Transform, all_layers: bool },
+    /// Set the colour to the pixel at the position,
+    /// from the image or from the reference image
+    Pick(Position),
+    Reference(RefCommand),
+}
+
+// -----------------------------------------------------------------------------
+//     - Target -
+//     The pixels a command applies to
+// -----------------------------------------------------------------------------
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// `x,y` or `x y`
+    Pixel(Position),
+    /// `x,y x,y`: two opposite corners, both included
+    Rect(Position, Position),
+    /// `*`: the whole layer
+    Layer,
+}
+
+// -----------------------------------------------------------------------------
+//     - Anchor -
+//     The part of the image that stays in place when resizing
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const NAMES: &'static [&'static str] = &[
+        "topleft",
+        "top",
+        "topright",
+        "left",
+        "centre",
+        "right",
+        "bottomleft",
+        "bottom",
+        "bottomright",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let anchor = match name {
+            "topleft" => Anchor::TopLeft,
+            "top" => Anchor::Top,
+            "topright" => Anchor::TopRight,
+            "left" => Anchor::Left,
+            "centre" | "center" => Anchor::Centre,
+            "right" => Anchor::Right,
+            "bottomleft" => Anchor::BottomLeft,
+            "bottom" => Anchor::Bottom,
+            "bottomright" => Anchor::BottomRight,
+            _ => return None,
+        };
+
+        Some(anchor)
+    }
+
+    /// Where the anchor is along each axis, in halves:
+    /// 0 is the left or top edge, 1 the middle and 2 the right or bottom edge.
+    pub fn halves(self) -> (i32, i32) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Centre => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Transform -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    /// Quarter turns clockwise: 1, 2 or 3
+    Rotate(u8),
+    /// Pixels shifted past the edge are lost, or come back on the other side with `wrap`
+    Shift { x: i32, y: i32, wrap: bool },
+}
+
+// -----------------------------------------------------------------------------
+//     - Reference image -
+//     `:ref <path>` loads an image to trace over,
+//     the other `:ref` commands change how it's shown
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefCommand {
+    Load(PathBuf),
+    Clear,
+    Opacity(u8),
+    /// Position of the top left pixel of the reference, in image pixels
+    Offset(i32, i32),
+    /// Image pixels per reference pixel
+    Scale(f32),
+    Above,
+    Below,
+}
+
+// -----------------------------------------------------------------------------
+//     - Direction -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            _ => None,
+        }
+    }
 }
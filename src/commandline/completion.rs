@@ -0,0 +1,218 @@
+use std::fs::read_dir;
+use std::path::Path;
+
+use super::parser::split_range;
+use super::registry::{self, ArgKind, COMMANDS};
+
+// -----------------------------------------------------------------------------
+//     - Candidates -
+//     Completion candidates the command line can't find on its own,
+//     sent by whoever owns them.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, Default)]
+pub struct Candidates {
+    /// Names of aliases and `:command`s
+    pub aliases: Vec<String>,
+    pub layers: Vec<String>,
+    pub lua_globals: Vec<String>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Completion -
+//     The word before the caret is replaced with one candidate at a time.
+// -----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Completion {
+    /// Char index where the word being completed starts
+    pub start: usize,
+    pub candidates: Vec<String>,
+    pub index: usize,
+}
+
+impl Completion {
+    /// Find the candidates for the word ending at the caret.
+    /// `before_caret` is the text of the command line up to the caret.
+    pub fn new(before_caret: &str, known: &Candidates) -> Option<Self> {
+        let (range, line) = split_range(before_caret.strip_prefix(':')?);
+        // Char index of the command name, after the `:` and the range
+        let offset = range.len() + 1;
+
+        let (start, candidates) = match line.find(char::is_whitespace) {
+            None => (offset, commands(line, &known.aliases)),
+            Some(end) => {
+                let spec = registry::find(&line[..end])?;
+                let start = word_start(line, spec.args);
+                let word = &line[start..];
+
+                let candidates = match spec.args {
+                    ArgKind::Path => paths(word),
+                    ArgKind::Layer => prefixed(&known.layers, word),
+                    ArgKind::Lua => prefixed(&known.lua_globals, word),
+                    ArgKind::None | ArgKind::Numbers | ArgKind::Text => vec![],
+                };
+
+                (line[..start].chars().count() + offset, candidates)
+            }
+        };
+
+        match candidates.is_empty() {
+            true => None,
+            false => Some(Self { start, candidates, index: 0 }),
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.candidates[self.index]
+    }
+
+    pub fn next(&mut self) {
+        self.index = (self.index + 1) % self.candidates.len();
+    }
+
+    pub fn prev(&mut self) {
+        self.index = (self.index + self.candidates.len() - 1) % self.candidates.len();
+    }
+}
+
+/// Byte index of the start of the last word in `line`.
+/// Lua code is split on anything that can't be part of a name,
+/// so `:lua print(set` completes `set`.
+fn word_start(line: &str, args: ArgKind) -> usize {
+    let is_separator = |c: char| match args {
+        ArgKind::Lua => !(c.is_alphanumeric() || c == '_' || c == '.'),
+        _ => c.is_whitespace(),
+    };
+
+    line.rfind(is_separator).map(|i| i + 1).unwrap_or(0)
+}
+
+fn prefixed(candidates: &[String], word: &str) -> Vec<String> {
+    candidates
+        .iter()
+        .filter(|c| c.starts_with(word))
+        .cloned()
+        .collect()
+}
+
+fn commands(word: &str, aliases: &[String]) -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|spec| spec.name.to_owned())
+        .filter(|name| !aliases.contains(name))
+        .chain(aliases.iter().cloned())
+        .filter(|name| name.starts_with(word))
+        .collect()
+}
+
+/// Files and directories starting with `word`.
+/// Directories end with a `/` so completing again continues inside them.
+/// Hidden files are only included if `word` names one.
+fn paths(word: &str) -> Vec<String> {
+    let (dir, file) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+
+    let entries = match read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut paths = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+
+            let slash = match entry.path().is_dir() {
+                true => "/",
+                false => "",
+            };
+
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn complete(line: &str) -> Vec<String> {
+        let known = Candidates {
+            aliases: vec!["newsprite".into()],
+            layers: vec!["1".into(), "2".into(), "12".into()],
+            lua_globals: vec!["print".into(), "setColor".into(), "putPixel".into()],
+        };
+
+        Completion::new(line, &known).map(|c| c.candidates).unwrap_or_default()
+    }
+
+    #[test]
+    fn command_names() {
+        assert_eq!(complete(":ne"), vec!["new", "newlayer", "newsprite"]);
+        assert_eq!(complete(":").len(), COMMANDS.len() + 1);
+        assert!(complete(":xyz").is_empty());
+    }
+
+    #[test]
+    fn layer_names() {
+        assert_eq!(complete(":layer 1"), vec!["1", "12"]);
+        assert!(Completion::new(":layer 1", &Candidates::default()).is_none());
+    }
+
+    #[test]
+    fn after_a_range() {
+        let completion = Completion::new(":3,5la", &Candidates::default()).unwrap();
+        assert_eq!(completion.current(), "layer");
+        assert_eq!(completion.start, 4);
+        assert_eq!(complete(":2,3layer 1"), vec!["1", "12"]);
+    }
+
+    #[test]
+    fn lua_globals() {
+        let completion = Completion::new(":lua print(set", &Candidates {
+            lua_globals: vec!["setColor".into()],
+            ..Default::default()
+        }).unwrap();
+
+        assert_eq!(completion.current(), "setColor");
+        assert_eq!(completion.start, ":lua print(".len());
+    }
+
+    #[test]
+    fn file_paths() {
+        let dir = std::env::temp_dir().join(format!("mixel-completion-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sprites")).unwrap();
+        std::fs::write(dir.join("sprites/hero.png"), b"").unwrap();
+        let dir_name = dir.display();
+
+        let paths = complete(&format!(":w {}/sp", dir_name));
+        assert_eq!(paths, vec![format!("{}/sprites/", dir_name)]);
+        let paths = complete(&format!(":e {}/sprites/", dir_name));
+        assert_eq!(paths, vec![format!("{}/sprites/hero.png", dir_name)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_candidates_for_numbers() {
+        assert!(complete(":put 1").is_empty());
+    }
+
+    #[test]
+    fn cycle() {
+        let mut completion = Completion::new(":ne", &Candidates::default()).unwrap();
+        completion.next();
+        assert_eq!(completion.current(), "newlayer");
+        completion.next();
+        assert_eq!(completion.current(), "new");
+        completion.prev();
+        assert_eq!(completion.current(), "newlayer");
+    }
+}
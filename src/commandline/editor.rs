@@ -0,0 +1,202 @@
+// -----------------------------------------------------------------------------
+//     - Line editor -
+//     Text and caret of the command line.
+//     The caret is a char index, not a byte index.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    chars: Vec<char>,
+    caret: usize,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Replace the text and move the caret to the end
+    pub fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.caret = self.chars.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.clear();
+        self.caret = 0;
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.chars.insert(self.caret, c);
+        self.caret += 1;
+    }
+
+    /// Length of the `:` prompt, which the deletes leave in place
+    fn prompt_len(&self) -> usize {
+        match self.chars.first() {
+            Some(':') => 1,
+            _ => 0,
+        }
+    }
+
+    /// Delete the char before the caret
+    pub fn backspace(&mut self) {
+        if self.caret > self.prompt_len() {
+            self.caret -= 1;
+            self.chars.remove(self.caret);
+        }
+    }
+
+    /// Delete the char under the caret
+    pub fn delete(&mut self) {
+        if self.caret >= self.prompt_len() && self.caret < self.chars.len() {
+            self.chars.remove(self.caret);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.caret = self.caret.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.caret = (self.caret + 1).min(self.chars.len());
+    }
+
+    pub fn home(&mut self) {
+        self.caret = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.caret = self.chars.len();
+    }
+
+    /// `<C-w>`: delete the word before the caret, and any whitespace after it
+    pub fn delete_word(&mut self) {
+        let min = self.prompt_len().min(self.caret);
+        let mut start = self.caret;
+        while start > min && self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > min && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+
+        self.chars.drain(start..self.caret);
+        self.caret = start;
+    }
+
+    /// Replace the text from `start` up to the caret, leaving the caret after the new text
+    pub fn replace(&mut self, start: usize, text: &str) {
+        let start = start.min(self.caret);
+        self.chars.splice(start..self.caret, text.chars());
+        self.caret = start + text.chars().count();
+    }
+
+    /// `<C-u>`: delete everything before the caret, except the `:` prompt
+    pub fn delete_to_start(&mut self) {
+        let start = self.prompt_len().min(self.caret);
+
+        self.chars.drain(start..self.caret);
+        self.caret = start;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn editor(text: &str) -> LineEditor {
+        let mut editor = LineEditor::new();
+        editor.set(text);
+        editor
+    }
+
+    #[test]
+    fn insert_in_the_middle() {
+        let mut editor = editor(":pt");
+        editor.left();
+        editor.insert('u');
+        assert_eq!(editor.text(), ":put");
+        assert_eq!(editor.caret(), 3);
+    }
+
+    #[test]
+    fn backspace_and_delete() {
+        let mut editor = editor(":puut");
+        editor.left();
+        editor.backspace();
+        assert_eq!(editor.text(), ":put");
+        editor.home();
+        editor.delete();
+        assert_eq!(editor.text(), ":put");
+        editor.right();
+        editor.delete();
+        assert_eq!(editor.text(), ":ut");
+    }
+
+    #[test]
+    fn backspace_keeps_the_prompt() {
+        let mut editor = editor(":p");
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(editor.text(), ":");
+        assert_eq!(editor.caret(), 1);
+    }
+
+    #[test]
+    fn caret_stays_in_bounds() {
+        let mut editor = editor("ab");
+        editor.right();
+        assert_eq!(editor.caret(), 2);
+        editor.home();
+        editor.left();
+        assert_eq!(editor.caret(), 0);
+    }
+
+    #[test]
+    fn delete_word() {
+        let mut editor = editor(":colour 1 2  ");
+        editor.delete_word();
+        assert_eq!(editor.text(), ":colour 1 ");
+        editor.delete_word();
+        editor.delete_word();
+        assert_eq!(editor.text(), ":");
+        editor.delete_word();
+        assert_eq!(editor.text(), ":");
+    }
+
+    #[test]
+    fn replace_before_caret() {
+        let mut editor = editor(":w sr rest");
+        editor.home();
+        (0..5).for_each(|_| editor.right());
+        editor.replace(3, "src/");
+        assert_eq!(editor.text(), ":w src/ rest");
+        assert_eq!(editor.caret(), 7);
+    }
+
+    #[test]
+    fn delete_to_start() {
+        let mut editor = editor(":put 1 2");
+        editor.left();
+        editor.left();
+        editor.delete_to_start();
+        assert_eq!(editor.text(), ": 2");
+        assert_eq!(editor.caret(), 1);
+
+        editor.home();
+        editor.delete_to_start();
+        assert_eq!(editor.text(), ": 2");
+    }
+}
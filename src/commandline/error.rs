@@ -0,0 +1,38 @@
+use std::fmt;
+
+// -----------------------------------------------------------------------------
+//     - Parse error -
+// -----------------------------------------------------------------------------
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    UnknownCommand(String),
+    /// The arguments are missing, or there are too many of them
+    Expected { what: String, command: String },
+    NoArgs(String),
+    NotANumber(String),
+    OutOfRange { value: String, command: String },
+    InvalidRange(String),
+    NoRange(String),
+    InvalidName(String),
+    RecursiveAlias(String),
+    InAlias { alias: String, error: Box<ParseError> },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            ParseError::Expected { what, command } => write!(f, "expected {} for {}", what, command),
+            ParseError::NoArgs(command) => write!(f, "{} doesn't take any arguments", command),
+            ParseError::NotANumber(value) => write!(f, "`{}` is not a number", value),
+            ParseError::OutOfRange { value, command } => write!(f, "`{}` is out of range for {}", value, command),
+            ParseError::InvalidRange(range) => write!(f, "`{}` is not a valid range", range),
+            ParseError::NoRange(command) => write!(f, "{} doesn't take a range", command),
+            ParseError::InvalidName(name) => write!(f, "`{}` is not a valid command name", name),
+            ParseError::RecursiveAlias(alias) => write!(f, "{} runs itself", alias),
+            ParseError::InAlias { alias, error } => write!(f, "in {}: {}", alias, error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,150 @@
+use std::fs::{create_dir_all, read_to_string, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use log::error;
+
+/// Number of entries kept, both in memory and when loading from disk
+const MAX_ENTRIES: usize = 1000;
+
+// -----------------------------------------------------------------------------
+//     - History -
+//     Previously run commands, oldest first.
+//
+//     Moving through the history only visits entries that start
+//     with the text typed before moving, so typing `:co` and pressing
+//     up goes to the last command starting with `:co`.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// Index of the entry being shown, `None` when editing a new line
+    index: Option<usize>,
+    /// Text typed before moving through the history
+    prefix: String,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self { entries, ..Default::default() }
+    }
+
+    /// Load the history from disk. Every new entry is appended to the file.
+    pub fn from_path(path: PathBuf) -> Self {
+        let entries = read_to_string(&path)
+            .map(|src| src.lines().filter(|l| !l.is_empty()).map(str::to_owned).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let skip = entries.len().saturating_sub(MAX_ENTRIES);
+        let mut inst = Self::new(entries.into_iter().skip(skip).collect());
+        inst.path = Some(path);
+        inst
+    }
+
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+
+        if line.trim_start_matches(':').trim().is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+
+        self.entries.push(line.to_owned());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+
+        if let Err(e) = self.save(line) {
+            error!("Failed to save command history: {:?}", e);
+        }
+    }
+
+    fn save(&self, line: &str) -> std::io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Stop moving through the history
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.prefix.clear();
+    }
+
+    /// Move to the previous entry starting with the prefix.
+    /// `current` is the text on the command line, and becomes the prefix
+    /// if this is the first move.
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        if self.index.is_none() {
+            self.prefix = current.to_owned();
+        }
+
+        let end = self.index.unwrap_or(self.entries.len());
+        let index = self.entries[..end].iter().rposition(|e| e.starts_with(&self.prefix))?;
+        self.index = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// Move to the next entry starting with the prefix.
+    /// Moving past the newest entry returns the prefix.
+    pub fn next(&mut self) -> Option<&str> {
+        let start = self.index? + 1;
+
+        match self.entries[start..].iter().position(|e| e.starts_with(&self.prefix)) {
+            Some(offset) => {
+                self.index = Some(start + offset);
+                Some(&self.entries[start + offset])
+            }
+            None => {
+                self.index = None;
+                Some(&self.prefix)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history() -> History {
+        History::new(vec![":colour 1 2 3".into(), ":put 1 1".into(), ":clear 1 1".into(), ":put 2 2".into()])
+    }
+
+    #[test]
+    fn move_through_history() {
+        let mut history = history();
+        assert_eq!(history.prev(""), Some(":put 2 2"));
+        assert_eq!(history.prev(""), Some(":clear 1 1"));
+        assert_eq!(history.next(), Some(":put 2 2"));
+        assert_eq!(history.next(), Some(""));
+        assert_eq!(history.next(), None);
+    }
+
+    #[test]
+    fn search_by_prefix() {
+        let mut history = history();
+        assert_eq!(history.prev(":p"), Some(":put 2 2"));
+        assert_eq!(history.prev(":p"), Some(":put 1 1"));
+        assert_eq!(history.prev(":p"), None);
+        assert_eq!(history.next(), Some(":put 2 2"));
+        assert_eq!(history.next(), Some(":p"));
+    }
+
+    #[test]
+    fn skip_duplicates_and_empty_lines() {
+        let mut history = history();
+        history.push(":put 2 2");
+        history.push("  ");
+        history.push(":");
+        assert_eq!(history.entries.len(), 4);
+    }
+}
@@ -1,22 +1,36 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use log::error;
-use nightmare::events::Key;
+use nightmare::events::{Key, Modifiers};
 use nightmare::pixels::{Pixel, Pixels};
 use nightmare::render2d::{Model, SimpleRenderer};
 use nightmare::text::{default_font_shader, Text, WordWrap};
 use nightmare::{Context, Position, Size, Texture, VertexData, Viewport};
 
 use crate::application::Mode;
+use crate::config::{data_dir, Action, Config, Ui};
 use crate::input::Input;
 use crate::listener::{Listener, MessageCtx};
 use crate::message::Message;
 use crate::Node;
 
 mod commands;
+mod completion;
+mod editor;
+mod error;
+mod history;
 mod parser;
+mod registry;
+pub mod script;
 
-pub use commands::Command;
-use parser::Parser;
+pub use commands::{Anchor, Command, Direction, RefCommand, Target, Transform};
+pub use completion::Candidates;
+pub use parser::Parser;
+pub use registry::valid_name as valid_command_name;
+use completion::Completion;
+use editor::LineEditor;
+use history::History;
 
 // -----------------------------------------------------------------------------
 //     - Command line -
@@ -27,14 +41,23 @@ pub struct CommandLine {
     caret: Caret,
     viewport: Viewport,
     text: Text,
-    input_buffer: String,
-    visible_buffer: String,
+    line: LineEditor,
+    history: History,
+    completion: Option<Completion>,
+    candidates: Candidates,
+    popup: Popup,
+    /// Shown in place of the command line until command mode is entered again
+    error: Option<String>,
+    /// Run again by `@:`
+    last: Option<String>,
+    /// Aliases defined with `:command`
+    defined: HashMap<String, String>,
     mode: Mode,
 }
 
 impl CommandLine {
-    pub fn new(size: Size, context: &mut Context) -> Result<Self> {
-        let font_size = 18.0;
+    pub fn new(size: Size, ui: &Ui, context: &mut Context) -> Result<Self> {
+        let font_size = ui.font_size;
 
         let viewport = Viewport::new(Position::new(0.0, 0.0), viewport_size(size, font_size));
 
@@ -42,10 +65,12 @@ impl CommandLine {
         let mut text_renderer = SimpleRenderer::new(context, viewport.view_projection())?;
         text_renderer.set_shader(shader, viewport.view_projection(), context);
 
-        let mut text = Text::from_path("/usr/share/fonts/TTF/Hack-Regular.ttf", font_size, WordWrap::NoWrap, context)?;
+        let text = line_text(ui, context)?;
 
-        text.position(Position::new(0.0, font_size / 1.7));
-        text.z_index(9999);
+        let history = match data_dir() {
+            Some(dir) => History::from_path(dir.join("history")),
+            None => History::default(),
+        };
 
         let inst = Self {
             text_renderer,
@@ -53,99 +78,255 @@ impl CommandLine {
             caret: Caret::new(font_size, context, &viewport)?,
             viewport,
             text,
-            input_buffer: String::new(),
-            visible_buffer: String::new(),
+            line: LineEditor::new(),
+            history,
+            completion: None,
+            candidates: Candidates::default(),
+            popup: Popup::new(size, ui, context)?,
+            error: None,
+            last: None,
+            defined: HashMap::new(),
             mode: Mode::Normal,
         };
 
         Ok(inst)
     }
 
-    fn input(&mut self, input: Input, context: &mut Context) -> Option<Command> {
+    /// Returns the line when it's submitted
+    fn input(&mut self, input: Input, modifiers: Modifiers, ctx: &mut MessageCtx) -> Option<String> {
         match self.mode {
             Mode::Command => {}
             _ => return None,
         }
 
-        match input {
-            Input::Char(c) if c.is_control() => {}
-            Input::Char(c) => {
-                self.input_buffer.push(c);
-                self.visible_buffer.push(c);
-                self.update_text(context);
+        if let Input::Key(Key::Tab) = input {
+            self.complete(modifiers.shift(), ctx);
+            return None;
+        }
+
+        self.completion = None;
+
+        match (input, modifiers.ctrl()) {
+            (Input::Char('w'), true) => self.line.delete_word(),
+            (Input::Char('u'), true) => self.line.delete_to_start(),
+            (Input::Char('a'), true) => self.line.home(),
+            (Input::Char('e'), true) => self.line.end(),
+            (Input::Char(_), true) => return None,
+            (Input::Char(c), false) if c.is_control() => return None,
+            (Input::Char(c), false) => {
+                self.history.reset();
+                self.line.insert(c);
             }
-            Input::Key(key) => match key {
-                Key::Back => {
-                    self.visible_buffer.pop();
-                    self.input_buffer.pop();
-                    self.update_text(context);
+            (Input::Key(key), _) => match key {
+                Key::Back => self.line.backspace(),
+                Key::Delete => self.line.delete(),
+                Key::Left => self.line.left(),
+                Key::Right => self.line.right(),
+                Key::Home => self.line.home(),
+                Key::End => self.line.end(),
+                Key::Up => {
+                    let current = self.line.text();
+                    if let Some(entry) = self.history.prev(&current) {
+                        let entry = entry.to_owned();
+                        self.line.set(&entry);
+                    }
+                }
+                Key::Down => {
+                    if let Some(entry) = self.history.next() {
+                        let entry = entry.to_owned();
+                        self.line.set(&entry);
+                    }
                 }
                 Key::Return => {
-                    let input = self.input_buffer.drain(..).collect::<String>();
-                    let parser = Parser::new(&input);
-                    let command = parser.parse();
-                    self.visible_buffer.clear();
-                    self.update_text(context);
-                    return Some(command);
+                    let input = self.line.text();
+                    self.history.push(&input);
+                    self.line.clear();
+                    if input.len() > 1 {
+                        self.last = Some(input.clone());
+                    }
+                    self.update_text(ctx.context);
+                    return Some(input);
                 }
-                _ => {}
+                _ => return None,
             },
-            Input::Mouse(_) => {}
-            Input::Scroll(_) => {}
+            (Input::Mouse(_), _) | (Input::Scroll(_), _) => return None,
         }
 
+        self.update_text(ctx.context);
         None
     }
 
-    fn update_text(&mut self, context: &mut Context) {
-        if let Err(e) = self.text.set_text(&self.visible_buffer) {
-            error!("Failed to set text: {:?}", e);
+    /// Config aliases, with the ones defined by `:command` on top
+    fn aliases(&self, config: &Config) -> HashMap<String, String> {
+        let mut aliases = config.aliases.clone();
+        aliases.extend(self.defined.iter().map(|(k, v)| (k.clone(), v.clone())));
+        aliases
+    }
+
+    fn run(&self, src: &str, config: &Config) -> Message {
+        let aliases = self.aliases(config);
+        match Parser::new(src).aliases(&aliases).parse() {
+            Ok(command) => Message::Command(command),
+            Err(e) => Message::CommandError(e.to_string()),
         }
+    }
 
-        while self.text.caret().x + self.caret.node.sprite.size.x > self.viewport.size().x {
-            if self.visible_buffer.is_empty() {
-                break;
+    /// Replace the word before the caret with the next completion candidate,
+    /// or the previous one if `back` is set.
+    fn complete(&mut self, back: bool, ctx: &mut MessageCtx) {
+        match &mut self.completion {
+            Some(completion) if back => completion.prev(),
+            Some(completion) => completion.next(),
+            None => {
+                let mut aliases = self.aliases(ctx.config).into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+                aliases.sort();
+                self.candidates.aliases = aliases;
+
+                let before_caret = self.line.text().chars().take(self.line.caret()).collect::<String>();
+                self.completion = Completion::new(&before_caret, &self.candidates);
             }
-            self.visible_buffer.drain(..1);
-            if let Err(e) = self.text.set_text(&self.visible_buffer) {
+        }
+
+        if let Some(completion) = &self.completion {
+            self.line.replace(completion.start, completion.current());
+        }
+
+        self.update_text(ctx.context);
+    }
+
+    /// Make the text, caret and popup again after the config changed,
+    /// for a new font or font size
+    fn reload_font(&mut self, ui: &Ui, context: &mut Context) -> Result<()> {
+        self.font_size = ui.font_size;
+        self.viewport.resize(viewport_size(*self.viewport.size(), self.font_size));
+        self.text = line_text(ui, context)?;
+        self.caret = Caret::new(self.font_size, context, &self.viewport)?;
+        self.popup = Popup::new(*self.popup.viewport.size(), ui, context)?;
+
+        match self.error.clone() {
+            Some(error) => self.show_error(&error, context),
+            None => self.update_text(context),
+        }
+
+        Ok(())
+    }
+
+    fn show_error(&mut self, error: &str, context: &mut Context) {
+        self.error = Some(error.to_owned());
+
+        if let Err(e) = self.text.set_text(error) {
+            error!("Failed to set text: {:?}", e);
+        }
+
+        let models = self.text.models();
+        self.text_renderer.load_data(&models, context);
+    }
+
+    fn update_text(&mut self, context: &mut Context) {
+        let chars = self.line.text().chars().collect::<Vec<_>>();
+        let caret = self.line.caret();
+        let max_x = self.viewport.size().x - self.caret.node.sprite.size.x;
+
+        // Scroll the start of the line out of view
+        // until the caret fits in the viewport
+        let mut start = 0;
+        loop {
+            let before_caret = chars[start..caret].iter().collect::<String>();
+            if let Err(e) = self.text.set_text(&before_caret) {
                 error!("Failed to set text: {:?}", e);
             }
+
+            if self.text.caret().x <= max_x || start == caret {
+                break;
+            }
+
+            start += 1;
         }
 
-        self.caret.node.transform.isometry.translation = Position::new(self.text.caret().x, self.font_size / 3.0).into();
+        let caret_x = self.text.caret().x;
+        self.caret.node.transform.isometry.translation = Position::new(caret_x, self.font_size / 3.0).into();
+
+        let visible = chars[start..].iter().collect::<String>();
+        if let Err(e) = self.text.set_text(&visible) {
+            error!("Failed to set text: {:?}", e);
+        }
 
         let models = self.text.models();
         self.text_renderer.load_data(&models, context);
+
+        self.popup.update(self.completion.as_ref(), context);
     }
 }
 
+/// The text of the command line, at the bottom of the window
+fn line_text(ui: &Ui, context: &mut Context) -> Result<Text> {
+    let mut text = Text::from_path(ui.font_path()?, ui.font_size, WordWrap::NoWrap, context)?;
+    text.position(Position::new(0.0, ui.font_size / 1.7));
+    text.z_index(9999);
+    Ok(text)
+}
+
 // -----------------------------------------------------------------------------
 //     - Listener -
 // -----------------------------------------------------------------------------
 impl Listener for CommandLine {
     fn message(&mut self, message: &Message, ctx: &mut MessageCtx) -> Message {
         match message {
-            Message::Input(input, _) => {
-                return self
-                    .input(*input, ctx.context)
-                    .map(Message::Command)
-                    .unwrap_or(Message::Noop)
+            Message::Input(input, modifiers) => {
+                return match self.input(*input, *modifiers, ctx) {
+                    Some(line) => self.run(&line, ctx.config),
+                    None => Message::Noop,
+                }
+            }
+            // Commands bound to keys
+            Message::Action(Action::Command(src)) => return self.run(src, ctx.config),
+            Message::Command(Command::Define { name, body }) => {
+                self.defined.insert(name.clone(), body.clone());
+            }
+            Message::CommandError(e) => {
+                error!("{}", e);
+                self.show_error(e, ctx.context);
             }
             Message::Resize(new_size) => {
                 self.viewport
                     .resize(viewport_size(*new_size, self.font_size));
+                self.popup.viewport.resize(*new_size);
             }
             Message::ModeChanged(mode) => {
                 self.mode = *mode;
-                self.visible_buffer.clear();
-                self.input_buffer.clear();
+                self.line.clear();
+                self.history.reset();
+                self.completion = None;
+                self.popup.update(None, ctx.context);
+
+                if let Mode::Command = mode {
+                    self.error = None;
+                    self.line.insert(':');
+                    self.update_text(ctx.context);
+                }
+            }
+            Message::Action(Action::RepeatCommand) => {
+                return match &self.last {
+                    Some(last) => self.run(last, ctx.config),
+                    None => Message::CommandError("no previous command".to_owned()),
+                }
+            }
+            Message::Candidates(candidates) => self.candidates = candidates.clone(),
+            Message::ConfigReloaded => {
+                if let Err(e) = self.reload_font(&ctx.config.ui, ctx.context) {
+                    return Message::CommandError(format!("failed to load the font: {}", e));
+                }
             }
             Message::CursorPos(_)
             | Message::Action(_)
+            | Message::RepeatAction(..)
+            | Message::Operator { .. }
             | Message::Command(_)
             | Message::CursorCoords(_)
-            // | Message::LayerChanged { .. }
+            | Message::PendingKeys(_)
+            | Message::LayerChanged { .. }
             | Message::ReloadPlugin(_)
+            | Message::Tick
             | Message::Noop => {}
         }
 
@@ -155,6 +336,11 @@ impl Listener for CommandLine {
     fn render(&mut self, ctx: &mut MessageCtx) {
         match self.mode {
             Mode::Command => {}
+            _ if self.error.is_some() => {
+                self.text.texture().bind();
+                self.text_renderer.render_instanced(ctx.context, 1);
+                return;
+            }
             _ => return,
         }
 
@@ -162,6 +348,10 @@ impl Listener for CommandLine {
 
         self.text_renderer.render_instanced(ctx.context, 1);
         self.caret.render(ctx.context, &self.viewport);
+
+        if self.completion.is_some() {
+            self.popup.render(ctx.context);
+        }
     }
 }
 
@@ -198,6 +388,65 @@ impl Caret {
     }
 }
 
+// -----------------------------------------------------------------------------
+//     - Popup -
+//     Completion candidates, shown above the command line
+// -----------------------------------------------------------------------------
+struct Popup {
+    renderer: SimpleRenderer<Model>,
+    viewport: Viewport,
+    text: Text,
+}
+
+impl Popup {
+    fn new(size: Size, ui: &Ui, context: &mut Context) -> Result<Self> {
+        let font_size = ui.font_size;
+        let font_path = ui.font_path()?;
+
+        let viewport = Viewport::new(Position::zeros(), size);
+        let shader = default_font_shader()?;
+        let mut renderer = SimpleRenderer::new(context, viewport.view_projection())?;
+        renderer.set_shader(shader, viewport.view_projection(), context);
+
+        let mut text = Text::from_path(font_path, font_size, WordWrap::NoWrap, context)?;
+        text.position(Position::new(0.0, font_size * 2.0 + font_size / 1.7));
+        text.z_index(9999);
+
+        let inst = Self { renderer, viewport, text };
+
+        Ok(inst)
+    }
+
+    /// Show every candidate, with the selected one in brackets
+    fn update(&mut self, completion: Option<&Completion>, context: &mut Context) {
+        let text = match completion {
+            Some(completion) => completion
+                .candidates
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match i == completion.index {
+                    true => format!("[{}]", c),
+                    false => c.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join("  "),
+            None => String::new(),
+        };
+
+        if let Err(e) = self.text.set_text(&text) {
+            error!("Failed to set text: {:?}", e);
+        }
+
+        let models = self.text.models();
+        self.renderer.load_data(&models, context);
+    }
+
+    fn render(&mut self, context: &mut Context) {
+        self.text.texture().bind();
+        self.renderer.render_instanced(context, 1);
+    }
+}
+
 // -----------------------------------------------------------------------------
 //     - Viewport size -
 //     Used when resizing
@@ -1,141 +1,418 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
 use log::info;
 use nightmare::{Position, Size};
 use nightmare::pixels::Pixel;
 
-// use crate::layout::Split;
-// use crate::canvas::LayerId;
-// use crate::plugins::{Arg, PluginCall};
-use super::commands::Command;
+use super::commands::{Command, Target};
+use super::error::ParseError;
+use super::registry::{self, ArgKind};
 
-macro_rules! or_noop {
-    ($e:expr) => {
-        match $e {
-            Some(val) => val,
-            None => return Command::Noop
-        }
-    }
+/// Aliases can run other aliases, but not more than this deep,
+/// so an alias that runs itself is an error rather than a hang.
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// Split the range in front of a command from the rest: `3,5layer hide`
+/// is split into `3,5` and `layer hide`.
+pub(super) fn split_range(src: &str) -> (&str, &str) {
+    let end = src
+        .find(|c: char| !(c.is_ascii_digit() || c == ','))
+        .unwrap_or(src.len());
+
+    src.split_at(end)
 }
 
+// -----------------------------------------------------------------------------
+//     - Parser -
+//     `:[range]command [args]`
+// -----------------------------------------------------------------------------
 pub struct Parser<'a> {
-    command: &'a str,
-    args: &'a str,
+    pub(super) range: &'a str,
+    pub(super) command: &'a str,
+    pub(super) args: &'a str,
+    aliases: Option<&'a HashMap<String, String>>,
+    depth: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(src: &'a str) -> Self {
-        let command_end = src.find(' ').unwrap_or(src.len());
-        let (command, args) = src.trim().split_at(command_end);
+        let src = src.trim().get(1..).unwrap_or("");
+        let (range, src) = split_range(src);
+        let command_end = src.find(char::is_whitespace).unwrap_or(src.len());
+        let (command, args) = src.split_at(command_end);
+
         Self {
-            command: command.trim().get(1..).unwrap_or(""),
+            range,
+            command,
             args: args.trim(),
+            aliases: None,
+            depth: 0,
         }
     }
 
-    pub fn parse(self) -> Command {
-
-        // TODO: this shouls resize
-        //       all the textures in all the layers.
-        //       Rename this to something other than extend
-        // macro_rules! extend {
-        //     ($dir:ident) => {
-        //         match self.args.parse::<i32>() {
-        //             Ok(n) => Command::Extend(Extent { $dir: n, ..Default::default() }),
-        //             Err(_) => Command::Noop,
-        //         }
-        //     }
-        // }
+    /// Aliases are `name -> commands`, where commands are separated by `;`.
+    /// An alias is used in place of a builtin command with the same name.
+    pub fn aliases(mut self, aliases: &'a HashMap<String, String>) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
 
+    pub fn parse(self) -> Result<Command, ParseError> {
         info!("{:?} | {}", self.command, self.args);
 
-        match self.command {
-            "q" => Command::Quit,
-            w@"w" | w@"w!" => Command::Save { path: self.args.to_owned(), overwrite: w == "w!" },
-            // "extendl" => extend!(left),
-            // "extendr" => extend!(right),
-            // "extendu" => extend!(up),
-            // "extendd" => extend!(down),
-            "put" => Command::Put(or_noop!(self.args_to_pos())),
-            "clear" => Command::Clear(or_noop!(self.args_to_pos())),
-            "new" => Command::NewImage(or_noop!(self.args_to_size())),
-            // "split" => Command::Split(Split::Horz),
-            // "splitv" => Command::Split(Split::Vert),
-            "close" => Command::CloseSelectedSplit,
-            "colour" | "color" => Command::SetColour(or_noop!(self.args_to_rgb())),
-            "alpha" => Command::SetAlpha(or_noop!(self.args_to_u8())),
-            // "layer" => Command::ChangeLayer(LayerId::from_display(or_noop!(self.args_to_usize()))),
-            // "newlayer" => Command::NewLayer,
-            // "removelayer" => Command::RemoveLayer,
-            "lua" => Command::Lua(self.args.to_owned()),
-            "log" => Command::Log(self.args.to_owned()),
-            _ => Command::Noop,
+        // Nothing but a `:`
+        if self.command.is_empty() && self.range.is_empty() {
+            return Ok(Command::Noop);
+        }
+
+        if self.command.is_empty() {
+            return Err(ParseError::UnknownCommand(self.range.to_owned()));
+        }
+
+        if let Some(body) = self.aliases.and_then(|aliases| aliases.get(self.command)) {
+            if !self.args.is_empty() {
+                return Err(ParseError::NoArgs(self.command.to_owned()));
+            }
+
+            if !self.range.is_empty() {
+                return Err(ParseError::NoRange(self.command.to_owned()));
+            }
+
+            // Only the innermost alias is named in the error
+            return self.expand(body).map_err(|e| match e {
+                e @ ParseError::InAlias { .. } => e,
+                e => ParseError::InAlias { alias: self.command.to_owned(), error: Box::new(e) },
+            });
         }
+
+        let spec = registry::find(self.command)
+            .ok_or_else(|| ParseError::UnknownCommand(self.command.to_owned()))?;
+
+        if spec.args == ArgKind::None && !self.args.is_empty() {
+            return Err(ParseError::NoArgs(self.command.to_owned()));
+        }
+
+        if !spec.range && !self.range.is_empty() {
+            return Err(ParseError::NoRange(self.command.to_owned()));
+        }
+
+        (spec.build)(&self)
     }
 
-    fn args_to_usize(&self) -> Option<usize> {
-        self.args.parse::<usize>().ok()
+    /// Parse every command in the alias body, into one `Command::Batch`
+    fn expand(&self, body: &str) -> Result<Command, ParseError> {
+        if self.depth == MAX_ALIAS_DEPTH {
+            return Err(ParseError::RecursiveAlias(self.command.to_owned()));
+        }
+
+        let mut commands = Vec::new();
+        for src in body.split(';').map(str::trim).filter(|src| !src.is_empty()) {
+            let src = match src.starts_with(':') {
+                true => src.to_owned(),
+                false => format!(":{}", src),
+            };
+
+            let mut parser = Parser::new(&src);
+            parser.aliases = self.aliases;
+            parser.depth = self.depth + 1;
+
+            match parser.parse()? {
+                Command::Batch(batch) => commands.extend(batch),
+                command => commands.push(command),
+            }
+        }
+
+        Ok(Command::Batch(commands))
     }
 
-    fn args_to_u8(&self) -> Option<u8> {
-        self.args.parse::<u8>().ok()
+    pub(super) fn expected(&self, what: impl Into<String>) -> ParseError {
+        ParseError::Expected { what: what.into(), command: self.command.to_owned() }
     }
 
-    fn args_to_rgb(&self) -> Option<Pixel> {
-        let mut parts = self.args.split_whitespace();
-        let r = parts.next().map(str::parse::<u8>).map(Result::ok).flatten()?;
-        let g = parts.next().map(str::parse::<u8>).map(Result::ok).flatten()?;
-        let b = parts.next().map(str::parse::<u8>).map(Result::ok).flatten()?;
+    pub(super) fn number<T: FromStr>(&self, part: &str) -> Result<T, ParseError> {
+        part.parse::<T>().map_err(|_| match part.parse::<f64>() {
+            Ok(_) => ParseError::OutOfRange { value: part.to_owned(), command: self.command.to_owned() },
+            Err(_) => ParseError::NotANumber(part.to_owned()),
+        })
+    }
 
-        let pixel = Pixel { r, g, b, a: 255, };
+    /// Exactly `count` numbers, separated by whitespace
+    fn numbers<T: FromStr>(&self, count: usize) -> Result<Vec<T>, ParseError> {
+        let parts = self.args.split_whitespace().collect::<Vec<_>>();
+        if parts.len() != count {
+            return match count {
+                1 => Err(self.expected("a number")),
+                n => Err(self.expected(format!("{} numbers", n))),
+            };
+        }
+
+        parts.into_iter().map(|part| self.number(part)).collect()
+    }
+
+    /// `x,y`
+    fn point(&self, part: &str) -> Result<Position, ParseError> {
+        let mut xy = part.split(',');
+        match (xy.next(), xy.next(), xy.next()) {
+            (Some(x), Some(y), None) => Ok(Position::new(self.number(x)?, self.number(y)?)),
+            _ => Err(self.expected("a position x,y")),
+        }
+    }
+
+    /// `N` or `N,M`, both included. Ranges are of layers, so they start at one.
+    pub(super) fn range(&self) -> Result<Option<RangeInclusive<usize>>, ParseError> {
+        if self.range.is_empty() {
+            return Ok(None);
+        }
+
+        let invalid = || ParseError::InvalidRange(self.range.to_owned());
+        let mut parts = self.range.split(',').map(str::parse::<usize>);
+
+        let range = match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(start)), None, None) => start..=start,
+            (Some(Ok(start)), Some(Ok(end)), None) => start..=end,
+            _ => return Err(invalid()),
+        };
+
+        match *range.start() > 0 && range.start() <= range.end() {
+            true => Ok(Some(range)),
+            false => Err(invalid()),
+        }
+    }
 
-        Some(pixel)
+    pub(super) fn args_to_target(&self) -> Result<Target, ParseError> {
+        let parts = self.args.split_whitespace().collect::<Vec<_>>();
+        match parts.as_slice() {
+            ["*"] => Ok(Target::Layer),
+            [point] => Ok(Target::Pixel(self.point(point)?)),
+            [from, to] if from.contains(',') => Ok(Target::Rect(self.point(from)?, self.point(to)?)),
+            [x, y] => Ok(Target::Pixel(Position::new(self.number(x)?, self.number(y)?))),
+            _ => Err(self.expected("a position x,y, two corners x,y x,y or *")),
+        }
     }
 
-    fn args_to_pos(&self) -> Option<Position> {
-        let mut parts = self.args.split_whitespace();
-        let x = parts.next().map(str::parse::<f32>).map(Result::ok).flatten()?;
-        let y = parts.next().map(str::parse::<f32>).map(Result::ok).flatten()?;
+    pub(super) fn args_to_path(&self) -> Result<String, ParseError> {
+        match self.args.is_empty() {
+            true => Err(self.expected("a path")),
+            false => Ok(self.args.to_owned()),
+        }
+    }
 
-        Some(Position::new(x, y))
+    pub(super) fn args_to_usize(&self) -> Result<usize, ParseError> {
+        Ok(self.numbers(1)?[0])
     }
 
-    fn args_to_size(&self) -> Option<Size> {
-        let mut parts = self.args.split_whitespace();
-        let width = parts.next().map(str::parse::<f32>).map(Result::ok).flatten()?;
-        let height = parts.next().map(str::parse::<f32>).map(Result::ok).flatten()?;
+    pub(super) fn args_to_u8(&self) -> Result<u8, ParseError> {
+        Ok(self.numbers(1)?[0])
+    }
+
+    pub(super) fn args_to_rgb(&self) -> Result<Pixel, ParseError> {
+        let rgb = self.numbers::<u8>(3)?;
+        let pixel = Pixel { r: rgb[0], g: rgb[1], b: rgb[2], a: 255, };
+
+        Ok(pixel)
+    }
 
-        Some(Size::new(width, height))
+    pub(super) fn args_to_size(&self) -> Result<Size, ParseError> {
+        let size = self.numbers::<f32>(2)?;
+        Ok(Size::new(size[0], size[1]))
+    }
+
+    /// Layers are numbered from one
+    pub(super) fn args_to_layer(&self) -> Result<usize, ParseError> {
+        match self.args_to_usize()? {
+            0 => Err(ParseError::OutOfRange { value: "0".to_owned(), command: self.command.to_owned() }),
+            layer => Ok(layer),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::commandline::commands::{Anchor, Direction, RefCommand, Transform};
+    use crate::layout::Split;
+
+    fn error(input: &str) -> String {
+        Parser::new(input).parse().unwrap_err().to_string()
+    }
 
     #[test]
     fn quit() {
         let input = ":q";
-        let output = matches!(Parser::new(input).parse(), Command::Quit);
+        let output = matches!(Parser::new(input).parse(), Ok(Command::Quit));
         assert!(output);
     }
 
-    // #[test]
-    // fn save_without_path() {
-    //     let input = ":w";
-    //     let output = matches!(Parser::new(input).parse(), Command::Noop);
-    //     assert!(output);
-    // }
-
-//     #[test]
-//     fn save() {
-//         let input = ":w test.png";
-//         let output = matches!(
-//             Parser::parse(input),
-//             Command::Save {
-//                 overwrite: false,
-//                 ..
-//             }
-//         );
-//         assert!(output);
-//     }
+    #[test]
+    fn save_without_path() {
+        assert_eq!(error(":w"), "expected a path for w");
+    }
+
+    #[test]
+    fn save() {
+        let input = ":w test.png";
+        let output = matches!(
+            Parser::new(input).parse(),
+            Ok(Command::Save {
+                overwrite: false,
+                ..
+            })
+        );
+        assert!(output);
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert_eq!(error(":foo 1 2"), "unknown command `foo`");
+    }
+
+    #[test]
+    fn wrong_number_of_args() {
+        assert_eq!(error(":colour 1 2"), "expected 3 numbers for colour");
+        assert_eq!(error(":alpha"), "expected a number for alpha");
+        assert_eq!(error(":q now"), "q doesn't take any arguments");
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert_eq!(error(":put 1 y"), "`y` is not a number");
+        assert_eq!(error(":alpha 300"), "`300` is out of range for alpha");
+        assert_eq!(error(":layer 0"), "`0` is out of range for layer");
+    }
+
+    #[test]
+    fn targets() {
+        let target = |input| match Parser::new(input).parse() {
+            Ok(Command::Put(target)) | Ok(Command::Clear(target)) => target,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(target(":put 1 2"), Target::Pixel(Position::new(1.0, 2.0)));
+        assert_eq!(target(":put 1,2"), Target::Pixel(Position::new(1.0, 2.0)));
+        assert_eq!(
+            target(":put 0,0 10,10"),
+            Target::Rect(Position::new(0.0, 0.0), Position::new(10.0, 10.0))
+        );
+        assert_eq!(target(":clear *"), Target::Layer);
+        assert_eq!(error(":put 0,0 10"), "expected a position x,y for put");
+    }
+
+    #[test]
+    fn layer_ranges() {
+        let output = Parser::new(":3,5layer hide").parse();
+        assert!(matches!(output, Ok(Command::LayerVisibility { layers: Some(r), visible: false }) if r == (3..=5)));
+
+        let output = Parser::new(":layer show").parse();
+        assert!(matches!(output, Ok(Command::LayerVisibility { layers: None, visible: true })));
+
+        assert!(matches!(Parser::new(":2layer").parse(), Ok(Command::ChangeLayer(2))));
+        assert_eq!(error(":5,3layer hide"), "`5,3` is not a valid range");
+        assert_eq!(error(":3q"), "q doesn't take a range");
+    }
+
+    #[test]
+    fn aliases() {
+        let mut aliases = HashMap::new();
+        aliases.insert("red".to_owned(), ":colour 255 0 0; alpha 128".to_owned());
+        aliases.insert("wipe".to_owned(), "red; clear *".to_owned());
+        aliases.insert("q".to_owned(), "log not quitting".to_owned());
+
+        let output = Parser::new(":wipe").aliases(&aliases).parse().unwrap();
+        let commands = match output {
+            Command::Batch(commands) => commands,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(commands[1], Command::SetAlpha(128)));
 
+        // Aliases win over builtin commands
+        let output = Parser::new(":q").aliases(&aliases).parse().unwrap();
+        assert!(matches!(output, Command::Batch(ref c) if matches!(c[0], Command::Log(_))));
+    }
+
+    #[test]
+    fn alias_errors() {
+        let mut aliases = HashMap::new();
+        aliases.insert("bad".to_owned(), "colour 1 2".to_owned());
+        aliases.insert("loop".to_owned(), "loop".to_owned());
+
+        let error = |input| Parser::new(input).aliases(&aliases).parse().unwrap_err().to_string();
+        assert_eq!(error(":bad"), "in bad: expected 3 numbers for colour");
+        assert_eq!(error(":loop"), "in loop: loop runs itself");
+        assert_eq!(error(":bad 1"), "bad doesn't take any arguments");
+    }
+
+    #[test]
+    fn define_command() {
+        let output = Parser::new(":command Red colour 255 0 0; alpha 255").parse();
+        assert!(matches!(output, Ok(Command::Define { ref name, ref body }) if name == "Red" && body == "colour 255 0 0; alpha 255"));
+        assert_eq!(error(":command 1x q"), "`1x` is not a valid command name");
+        assert_eq!(error(":command Red"), "expected a name and one or more commands for command");
+    }
+
+    #[test]
+    fn resize_and_extend() {
+        let output = Parser::new(":resize 16 8 bottomright").parse();
+        assert!(matches!(output, Ok(Command::Resize { width: 16, height: 8, anchor: Anchor::BottomRight })));
+        let output = Parser::new(":resize 16 8").parse();
+        assert!(matches!(output, Ok(Command::Resize { anchor: Anchor::TopLeft, .. })));
+        assert!(error(":resize 16 8 middle").starts_with("expected one of topleft"));
+        assert_eq!(error(":resize 16"), "expected a width, a height and an optional anchor for resize");
+
+        let output = Parser::new(":extend left -2").parse();
+        assert!(matches!(output, Ok(Command::Extend { direction: Direction::Left, amount: -2 })));
+        assert_eq!(error(":extend sideways 2"), "expected left, right, up or down for extend");
+    }
+
+    #[test]
+    fn transforms() {
+        let transform = |input| match Parser::new(input).parse() {
+            Ok(Command::Transform { transform, all_layers }) => (transform, all_layers),
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(transform(":flip h"), (Transform::FlipHorizontal, false));
+        assert_eq!(transform(":rotate 270 all"), (Transform::Rotate(3), true));
+        assert_eq!(transform(":shift -2 3 wrap"), (Transform::Shift { x: -2, y: 3, wrap: true }, false));
+        assert_eq!(transform(":shift 1 0 wrap all"), (Transform::Shift { x: 1, y: 0, wrap: true }, true));
+        assert_eq!(error(":rotate 45"), "expected 90, 180 or 270 for rotate");
+        assert_eq!(error(":shift 1"), "expected x and y offsets for shift");
+    }
+
+    #[test]
+    fn reference() {
+        let reference = |input| match Parser::new(input).parse() {
+            Ok(Command::Reference(command)) => command,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(reference(":ref concept art.png"), RefCommand::Load("concept art.png".into()));
+        assert_eq!(reference(":ref opacity 128"), RefCommand::Opacity(128));
+        assert_eq!(reference(":ref offset -4 2"), RefCommand::Offset(-4, 2));
+        assert_eq!(reference(":ref below"), RefCommand::Below);
+        assert_eq!(error(":ref scale 0"), "`0` is out of range for ref");
+        assert_eq!(error(":ref opacity"), "expected an opacity from 0 to 255 for ref");
+        assert!(matches!(Parser::new(":pick 1,2").parse(), Ok(Command::Pick(_))));
+    }
+
+    #[test]
+    fn splits() {
+        assert!(matches!(Parser::new(":split").parse(), Ok(Command::Split(Split::Horz))));
+        assert!(matches!(Parser::new(":vs").parse(), Ok(Command::Split(Split::Vert))));
+        assert!(matches!(Parser::new(":image 2").parse(), Ok(Command::ShowImage(2))));
+        assert_eq!(error(":image 0"), "`0` is out of range for image");
+        assert!(matches!(
+            Parser::new(":growsplit -32 0").parse(),
+            Ok(Command::GrowSplit { width: -32, height: 0 })
+        ));
+        assert_eq!(error(":growsplit 32"), "expected a width and a height in pixels for growsplit");
+        assert!(matches!(Parser::new(":equalize").parse(), Ok(Command::EqualiseSplits)));
+    }
+
+    #[test]
+    fn empty_command() {
+        assert!(matches!(Parser::new(":").parse(), Ok(Command::Noop)));
+    }
 }
@@ -0,0 +1,448 @@
+use super::commands::{Anchor, Command, Direction, RefCommand, Target, Transform};
+use super::error::ParseError;
+use super::parser::Parser;
+
+use crate::layout::Split;
+
+// -----------------------------------------------------------------------------
+//     - Arg kind -
+//     What a command takes as its argument,
+//     used to pick completion candidates.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArgKind {
+    None,
+    Numbers,
+    Path,
+    Layer,
+    Lua,
+    Text,
+}
+
+// -----------------------------------------------------------------------------
+//     - Command spec -
+// -----------------------------------------------------------------------------
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Other names for the same command, not offered when completing
+    pub aliases: &'static [&'static str],
+    pub args: ArgKind,
+    /// Takes a range of layers in front of the name: `:3,5layer hide`
+    pub range: bool,
+    pub build: fn(&Parser) -> Result<Command, ParseError>,
+}
+
+impl CommandSpec {
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "q",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Quit),
+    },
+    CommandSpec {
+        name: "w",
+        aliases: &["w!"],
+        args: ArgKind::Path,
+        range: false,
+        build: |p| Ok(Command::Save { path: p.args_to_path()?, overwrite: p.command == "w!" }),
+    },
+    CommandSpec {
+        name: "e",
+        aliases: &["edit"],
+        args: ArgKind::Path,
+        range: false,
+        build: |p| Ok(Command::Edit(p.args_to_path()?)),
+    },
+    CommandSpec {
+        name: "put",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::Put(p.args_to_target()?)),
+    },
+    CommandSpec {
+        name: "clear",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::Clear(p.args_to_target()?)),
+    },
+    CommandSpec {
+        name: "new",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::NewImage(p.args_to_size()?)),
+    },
+    CommandSpec {
+        name: "image",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::ShowImage(p.args_to_layer()?)),
+    },
+    CommandSpec {
+        name: "split",
+        aliases: &["sp"],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Split(Split::Horz)),
+    },
+    CommandSpec {
+        name: "splitv",
+        aliases: &["vs"],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Split(Split::Vert)),
+    },
+    CommandSpec {
+        name: "close",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::CloseSelectedSplit),
+    },
+    CommandSpec {
+        name: "growsplit",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: grow_split,
+    },
+    CommandSpec {
+        name: "equalise",
+        aliases: &["equalize"],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::EqualiseSplits),
+    },
+    CommandSpec {
+        name: "maximise",
+        aliases: &["maximize"],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::MaximiseSplit),
+    },
+    CommandSpec {
+        name: "colour",
+        aliases: &["color"],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::SetColour(p.args_to_rgb()?)),
+    },
+    CommandSpec {
+        name: "alpha",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| Ok(Command::SetAlpha(p.args_to_u8()?)),
+    },
+    CommandSpec {
+        name: "layer",
+        aliases: &[],
+        args: ArgKind::Layer,
+        range: true,
+        build: layer,
+    },
+    CommandSpec {
+        name: "newlayer",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::NewLayer),
+    },
+    CommandSpec {
+        name: "removelayer",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::RemoveLayer),
+    },
+    CommandSpec {
+        name: "lua",
+        aliases: &[],
+        args: ArgKind::Lua,
+        range: false,
+        build: |p| Ok(Command::Lua(p.args.to_owned())),
+    },
+    CommandSpec {
+        name: "log",
+        aliases: &[],
+        args: ArgKind::Text,
+        range: false,
+        build: |p| Ok(Command::Log(p.args.to_owned())),
+    },
+    CommandSpec {
+        name: "source",
+        aliases: &["so"],
+        args: ArgKind::Path,
+        range: false,
+        build: |p| Ok(Command::Source(p.args_to_path()?.into())),
+    },
+    CommandSpec {
+        name: "resize",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: resize,
+    },
+    CommandSpec {
+        name: "extend",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: extend,
+    },
+    CommandSpec {
+        name: "crop",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Crop),
+    },
+    CommandSpec {
+        name: "flip",
+        aliases: &[],
+        args: ArgKind::Text,
+        range: false,
+        build: flip,
+    },
+    CommandSpec {
+        name: "rotate",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: rotate,
+    },
+    CommandSpec {
+        name: "shift",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: shift,
+    },
+    CommandSpec {
+        name: "ref",
+        aliases: &[],
+        args: ArgKind::Path,
+        range: false,
+        build: reference,
+    },
+    CommandSpec {
+        name: "pick",
+        aliases: &[],
+        args: ArgKind::Numbers,
+        range: false,
+        build: |p| match p.args_to_target()? {
+            Target::Pixel(pos) => Ok(Command::Pick(pos)),
+            _ => Err(p.expected("a position x,y")),
+        },
+    },
+    CommandSpec {
+        name: "undo",
+        aliases: &["u"],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Undo),
+    },
+    CommandSpec {
+        name: "redo",
+        aliases: &[],
+        args: ArgKind::None,
+        range: false,
+        build: |_| Ok(Command::Redo),
+    },
+    CommandSpec {
+        name: "command",
+        aliases: &[],
+        args: ArgKind::Text,
+        range: false,
+        build: define,
+    },
+];
+
+/// Names of aliases and user defined commands start with a letter,
+/// and are made of letters, digits and `_`.
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// `:command Name commands`
+fn define(p: &Parser) -> Result<Command, ParseError> {
+    let mut parts = p.args.splitn(2, char::is_whitespace);
+    let (name, body) = match (parts.next(), parts.next().map(str::trim)) {
+        (Some(name), Some(body)) if !body.is_empty() => (name, body),
+        _ => return Err(p.expected("a name and one or more commands")),
+    };
+
+    if !valid_name(name) {
+        return Err(ParseError::InvalidName(name.to_owned()));
+    }
+
+    Ok(Command::Define { name: name.to_owned(), body: body.to_owned() })
+}
+
+/// `:layer N` changes layer, `:[range]layer hide|show` hides or shows
+/// the layers in the range, or the current layer without a range.
+/// `:Nlayer` is the same as `:layer N`.
+fn layer(p: &Parser) -> Result<Command, ParseError> {
+    let layers = p.range()?;
+
+    match (p.args, layers) {
+        ("hide", layers) => Ok(Command::LayerVisibility { layers, visible: false }),
+        ("show", layers) => Ok(Command::LayerVisibility { layers, visible: true }),
+        ("", Some(layers)) if layers.start() == layers.end() => Ok(Command::ChangeLayer(*layers.start())),
+        (_, Some(_)) => Err(p.expected("hide or show")),
+        (_, None) => Ok(Command::ChangeLayer(p.args_to_layer()?)),
+    }
+}
+
+/// `:resize W H [anchor]`, the anchor is the top left if it's left out
+fn resize(p: &Parser) -> Result<Command, ParseError> {
+    let parts = p.args.split_whitespace().collect::<Vec<_>>();
+    let (width, height, anchor) = match parts.as_slice() {
+        [width, height] => (width, height, Anchor::TopLeft),
+        [width, height, anchor] => match Anchor::from_name(anchor) {
+            Some(anchor) => (width, height, anchor),
+            None => return Err(p.expected(format!("one of {}", Anchor::NAMES.join(", ")))),
+        },
+        _ => return Err(p.expected("a width, a height and an optional anchor")),
+    };
+
+    Ok(Command::Resize { width: p.number(width)?, height: p.number(height)?, anchor })
+}
+
+/// `:extend left|right|up|down N`
+fn extend(p: &Parser) -> Result<Command, ParseError> {
+    let parts = p.args.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [direction, amount] => match Direction::from_name(direction) {
+            Some(direction) => Ok(Command::Extend { direction, amount: p.number(amount)? }),
+            None => Err(p.expected("left, right, up or down")),
+        },
+        _ => Err(p.expected("a direction and a number of pixels")),
+    }
+}
+
+/// `:growsplit W H`
+fn grow_split(p: &Parser) -> Result<Command, ParseError> {
+    let parts = p.args.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [width, height] => Ok(Command::GrowSplit { width: p.number(width)?, height: p.number(height)? }),
+        _ => Err(p.expected("a width and a height in pixels")),
+    }
+}
+
+/// The arguments, and whether the last one is `all`
+fn all_layers<'a>(p: &Parser<'a>) -> (Vec<&'a str>, bool) {
+    let mut parts = p.args.split_whitespace().collect::<Vec<_>>();
+    let all = parts.last() == Some(&"all");
+    if all {
+        parts.pop();
+    }
+
+    (parts, all)
+}
+
+/// `:flip h|v [all]`
+fn flip(p: &Parser) -> Result<Command, ParseError> {
+    let (parts, all_layers) = all_layers(p);
+    let transform = match parts.as_slice() {
+        ["h"] | ["horizontal"] => Transform::FlipHorizontal,
+        ["v"] | ["vertical"] => Transform::FlipVertical,
+        _ => return Err(p.expected("h or v")),
+    };
+
+    Ok(Command::Transform { transform, all_layers })
+}
+
+/// `:rotate 90|180|270 [all]`, clockwise
+fn rotate(p: &Parser) -> Result<Command, ParseError> {
+    let (parts, all_layers) = all_layers(p);
+    let turns = match parts.as_slice() {
+        ["90"] => 1,
+        ["180"] => 2,
+        ["270"] => 3,
+        _ => return Err(p.expected("90, 180 or 270")),
+    };
+
+    Ok(Command::Transform { transform: Transform::Rotate(turns), all_layers })
+}
+
+/// `:shift X Y [wrap] [all]`
+fn shift(p: &Parser) -> Result<Command, ParseError> {
+    let (mut parts, all_layers) = all_layers(p);
+    let wrap = parts.last() == Some(&"wrap");
+    if wrap {
+        parts.pop();
+    }
+
+    match parts.as_slice() {
+        [x, y] => {
+            let transform = Transform::Shift { x: p.number(x)?, y: p.number(y)?, wrap };
+            Ok(Command::Transform { transform, all_layers })
+        }
+        _ => Err(p.expected("x and y offsets")),
+    }
+}
+
+/// `:ref <path>`, `:ref clear`, `:ref opacity N`, `:ref offset X Y`,
+/// `:ref scale N`, `:ref above` and `:ref below`
+fn reference(p: &Parser) -> Result<Command, ParseError> {
+    let parts = p.args.split_whitespace().collect::<Vec<_>>();
+    let command = match parts.as_slice() {
+        [] => return Err(p.expected("a path")),
+        ["clear"] => RefCommand::Clear,
+        ["above"] => RefCommand::Above,
+        ["below"] => RefCommand::Below,
+        ["opacity", opacity] => RefCommand::Opacity(p.number(opacity)?),
+        ["offset", x, y] => RefCommand::Offset(p.number(x)?, p.number(y)?),
+        ["scale", scale] => match p.number::<f32>(scale)? {
+            scale if scale > 0.0 => RefCommand::Scale(scale),
+            _ => return Err(ParseError::OutOfRange { value: scale.to_string(), command: p.command.to_owned() }),
+        },
+        ["opacity", ..] => return Err(p.expected("an opacity from 0 to 255")),
+        ["offset", ..] => return Err(p.expected("x and y offsets")),
+        ["scale", ..] => return Err(p.expected("a scale")),
+        _ => RefCommand::Load(p.args_to_path()?.into()),
+    };
+
+    Ok(Command::Reference(command))
+}
+
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.matches(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (i, spec) in COMMANDS.iter().enumerate() {
+            let names = std::iter::once(&spec.name).chain(spec.aliases);
+            for name in names {
+                let found = COMMANDS.iter().position(|s| s.matches(name));
+                assert_eq!(found, Some(i), "`{}` is used by more than one command", name);
+            }
+        }
+    }
+
+    #[test]
+    fn find_by_alias() {
+        assert_eq!(find("color").map(|s| s.name), Some("colour"));
+        assert!(find("nope").is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Scripts can source other scripts, but not more than this deep
+pub const MAX_SOURCE_DEPTH: usize = 16;
+
+// -----------------------------------------------------------------------------
+//     - Script -
+//     A file of commands, one per line.
+//     Blank lines and lines starting with `#` are skipped,
+//     and the leading `:` is optional.
+// -----------------------------------------------------------------------------
+/// Every command in the script with its one based line number
+pub fn lines(src: &str) -> Vec<(usize, String)> {
+    src.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !(line.is_empty() || line.starts_with('#')))
+        .map(|(line_no, line)| match line.starts_with(':') {
+            true => (line_no, line.to_owned()),
+            false => (line_no, format!(":{}", line)),
+        })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+//     - Sink -
+//     Runs the commands of a script: the editor, or a document without a window
+// -----------------------------------------------------------------------------
+pub trait Sink {
+    /// Run a single command, e.g `:put 1,1`
+    fn run_command(&mut self, line: String) -> Result<()>;
+
+    /// Number of scripts being sourced
+    fn depth(&mut self) -> &mut usize;
+
+    /// True once the script should stop, e.g after `:q`
+    fn stopped(&self) -> bool {
+        false
+    }
+}
+
+/// Run every command in a script file, in order.
+/// Stops at the first command that fails, with the file and line number in the error.
+pub fn source(sink: &mut impl Sink, path: &Path) -> Result<()> {
+    if *sink.depth() == MAX_SOURCE_DEPTH {
+        bail!("{}: too many nested scripts", path.display());
+    }
+
+    let src = read_to_string(path).with_context(|| format!("Failed to read \"{}\"", path.display()))?;
+
+    *sink.depth() += 1;
+    let result = run_lines(sink, path, &src);
+    *sink.depth() -= 1;
+
+    result
+}
+
+/// Run the commands in `src`, `path` is only used in the errors
+pub fn run_lines(sink: &mut impl Sink, path: &Path, src: &str) -> Result<()> {
+    for (line_no, line) in lines(src) {
+        if sink.stopped() {
+            break;
+        }
+
+        if let Err(e) = sink.run_command(line) {
+            bail!("{}:{}: {}", path.display(), line_no, e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skip_blanks_and_comments() {
+        let src = "# Setup\n:new 32 32\n\n  colour 255 0 0\n# done\n";
+        let lines = lines(src);
+        assert_eq!(lines, vec![(2, ":new 32 32".to_owned()), (4, ":colour 255 0 0".to_owned())]);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        commands: Vec<String>,
+        depth: usize,
+    }
+
+    impl Sink for Recorder {
+        fn run_command(&mut self, line: String) -> Result<()> {
+            if line == ":fail" {
+                bail!("failed");
+            }
+
+            self.commands.push(line);
+            Ok(())
+        }
+
+        fn depth(&mut self) -> &mut usize {
+            &mut self.depth
+        }
+
+        fn stopped(&self) -> bool {
+            self.commands.last().map(|c| c == ":q").unwrap_or(false)
+        }
+    }
+
+    #[test]
+    fn run_until_error_or_stop() {
+        let mut sink = Recorder::default();
+        let error = run_lines(&mut sink, Path::new("a.mx"), "new 1 1\n\nfail\nput *").unwrap_err();
+        assert_eq!(error.to_string(), "a.mx:3: failed");
+        assert_eq!(sink.commands, vec![":new 1 1".to_owned()]);
+
+        let mut sink = Recorder::default();
+        run_lines(&mut sink, Path::new("a.mx"), "q\nput *").unwrap();
+        assert_eq!(sink.commands, vec![":q".to_owned()]);
+    }
+
+    #[test]
+    fn nested_too_deep() {
+        let mut sink = Recorder { depth: MAX_SOURCE_DEPTH, ..Recorder::default() };
+        let error = source(&mut sink, Path::new("a.mx")).unwrap_err();
+        assert_eq!(error.to_string(), "a.mx: too many nested scripts");
+    }
+}
@@ -1,9 +1,18 @@
+use std::str::FromStr;
+
 use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use serde::de::value::Error as ValueError;
+
+use crate::input::Operator;
 
 // -----------------------------------------------------------------------------
 //     - Actions -
+//     The name of an action in the config is the snake case
+//     version of the variant name, e.g `NextXPixel` is `next_x_pixel`.
 // -----------------------------------------------------------------------------
-#[derive(Debug, Copy, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     Left,
     Right,
@@ -19,8 +28,10 @@ pub enum Action {
     CanvasRight,
     CanvasUp,
     CanvasDown,
-    
+
+    #[serde(rename = "zoom_in")]
     CanvasZoomIn,
+    #[serde(rename = "zoom_out")]
     CanvasZoomOut,
 
     NextXPixel,
@@ -31,5 +42,106 @@ pub enum Action {
     SplitViewportVert,
     SplitViewportHorz,
 
+    /// Select the split next to the selected one
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+
+    Erase,
+    Paint,
+    Yank,
+    /// Paste the pixels from the last yank at the cursor
+    Paste,
+
+    Fill,
+
+    /// Show the image repeated around itself, to check that it tiles
+    ToggleTilePreview,
+    /// Show or hide the pixel and tile grid
+    ToggleGrid,
+    /// Set the colour from the pixel under the cursor, or from the reference image
+    PickColour,
+
+    /// Run the last command from the command line again
+    RepeatCommand,
+
+    /// Switch to insert mode
+    InsertMode,
+    /// Switch to visual mode, selecting from the cursor
+    VisualMode,
+
+    /// Run a command, bound as `":<command>"`
+    #[serde(skip)]
+    Command(String),
+    /// Run Lua code, bound as `"lua:<code>"`
+    #[serde(skip)]
+    Lua(String),
+
+    /// Binding a key to `noop` unbinds it
     Noop,
 }
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(':') {
+            return Ok(Action::Command(s.to_owned()));
+        }
+
+        if let Some(code) = s.strip_prefix("lua:") {
+            return Ok(Action::Lua(code.trim().to_owned()));
+        }
+
+        let de: serde::de::value::StrDeserializer<ValueError> = s.into_deserializer();
+        Action::deserialize(de).map_err(|_| format!("\"{}\" is not a valid action", s))
+    }
+}
+
+impl Action {
+    /// Actions that move the cursor and can therefore
+    /// be used as the motion of an operator.
+    pub fn is_motion(&self) -> bool {
+        use Action::*;
+        matches!(
+            self,
+            Left | Right | Up | Down | UpLeft | UpRight | DownLeft | DownRight
+        )
+    }
+
+    /// The operator this action starts, if any.
+    pub fn operator(&self) -> Option<Operator> {
+        match self {
+            Action::Erase => Some(Operator::Erase),
+            Action::Paint => Some(Operator::Paint),
+            Action::Yank => Some(Operator::Yank),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_action_names() {
+        assert_eq!("left".parse::<Action>().unwrap(), Action::Left);
+        assert_eq!("next_x_pixel".parse::<Action>().unwrap(), Action::NextXPixel);
+        assert_eq!("zoom_in".parse::<Action>().unwrap(), Action::CanvasZoomIn);
+        assert_eq!("noop".parse::<Action>().unwrap(), Action::Noop);
+    }
+
+    #[test]
+    fn parse_command_and_lua() {
+        assert_eq!(":w out.png".parse::<Action>().unwrap(), Action::Command(":w out.png".to_owned()));
+        assert_eq!("lua: Lib.thing()".parse::<Action>().unwrap(), Action::Lua("Lib.thing()".to_owned()));
+    }
+
+    #[test]
+    fn parse_invalid_action() {
+        assert!("not_an_action".parse::<Action>().is_err());
+        assert!("command".parse::<Action>().is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use nightmare::events::Modifiers;
+
+use super::Action;
+use crate::input::Input;
+
+/// A single key press: the input and the modifiers held at the time.
+pub type KeyPress = (Input, Modifiers);
+
+// -----------------------------------------------------------------------------
+//     - Match -
+// -----------------------------------------------------------------------------
+#[derive(Debug)]
+pub enum Match {
+    /// No binding starts with these keys
+    None,
+    /// These keys are the start of one or more bindings
+    Pending,
+    /// These keys are bound to an action, and nothing longer starts with them
+    Exact(Action),
+    /// These keys are bound to an action, but they are also the start of longer
+    /// bindings. The action should fire once the timeout has passed.
+    Ambiguous(Action),
+}
+
+// -----------------------------------------------------------------------------
+//     - Key map -
+//     Trie of key presses
+// -----------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct KeyMap {
+    action: Option<Action>,
+    children: HashMap<KeyPress, KeyMap>,
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, keys: &[KeyPress], action: Action) {
+        match keys.split_first() {
+            None => self.action = Some(action),
+            Some((key, rest)) => self.children.entry(*key).or_default().insert(rest, action),
+        }
+    }
+
+    pub fn lookup(&self, keys: &[KeyPress]) -> Match {
+        let mut node = self;
+        for key in keys {
+            node = match node.children.get(key) {
+                Some(node) => node,
+                None => return Match::None,
+            };
+        }
+
+        match (node.action.clone(), node.children.is_empty()) {
+            (Some(action), true) => Match::Exact(action),
+            (Some(action), false) => Match::Ambiguous(action),
+            (None, false) => Match::Pending,
+            (None, true) => Match::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(s: &str) -> Vec<KeyPress> {
+        s.chars().map(|c| (Input::Char(c), Modifiers::empty())).collect()
+    }
+
+    #[test]
+    fn exact_and_pending() {
+        let mut map = KeyMap::new();
+        map.insert(&keys("gg"), Action::Up);
+
+        assert!(matches!(map.lookup(&keys("g")), Match::Pending));
+        assert!(matches!(map.lookup(&keys("gg")), Match::Exact(Action::Up)));
+        assert!(matches!(map.lookup(&keys("gx")), Match::None));
+    }
+
+    #[test]
+    fn ambiguous() {
+        let mut map = KeyMap::new();
+        map.insert(&keys("z"), Action::CanvasZoomIn);
+        map.insert(&keys("zz"), Action::CanvasZoomOut);
+
+        assert!(matches!(map.lookup(&keys("z")), Match::Ambiguous(Action::CanvasZoomIn)));
+        assert!(matches!(map.lookup(&keys("zz")), Match::Exact(Action::CanvasZoomOut)));
+    }
+}
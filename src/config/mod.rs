@@ -1,36 +1,181 @@
 use std::collections::HashMap;
-use std::path::Path;
-use std::fs::read as read_data;
+use std::env::{current_dir, var_os};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
-use nightmare::events::Modifiers;
+use figment::Figment;
+use figment::providers::{Format, Toml};
 use serde::Deserialize;
 
-use crate::input::Input;
+use crate::application::Mode;
 
 mod actions;
+mod keymap;
 mod parse;
+mod ui;
+mod validate;
 
-use parse::parse_input;
+use parse::parse_sequence;
+pub use parse::{format_input, normalise};
 pub use actions::Action;
+pub use keymap::{KeyMap, KeyPress, Match};
+pub use ui::{Colour, Ui};
+pub use validate::{ConfigErrors, Diagnostic};
+
+/// Built in config, every other config is merged on top of this.
+const DEFAULT_CONFIG: &str = include_str!("default.toml");
+
+/// Config file in the working directory, for project specific settings.
+pub const PROJECT_CONFIG: &str = ".mixel.toml";
+
+// -----------------------------------------------------------------------------
+//     - Paths -
+// -----------------------------------------------------------------------------
+/// `$XDG_CONFIG_HOME/mixel`, or `~/.config/mixel` if `XDG_CONFIG_HOME` isn't set.
+pub fn config_dir() -> Option<PathBuf> {
+    let xdg = var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+
+    let base = match xdg {
+        Some(path) => path,
+        None => PathBuf::from(var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join("mixel"))
+}
+
+/// `$XDG_DATA_HOME/mixel`, or `~/.local/share/mixel` if `XDG_DATA_HOME` isn't set.
+pub fn data_dir() -> Option<PathBuf> {
+    let xdg = var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+
+    let base = match xdg {
+        Some(path) => path,
+        None => PathBuf::from(var_os("HOME")?).join(".local").join("share"),
+    };
+
+    Some(base.join("mixel"))
+}
+
+/// `$XDG_CACHE_HOME/mixel`, or `~/.cache/mixel` if `XDG_CACHE_HOME` isn't set.
+pub fn cache_dir() -> Option<PathBuf> {
+    let xdg = var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+
+    let base = match xdg {
+        Some(path) => path,
+        None => PathBuf::from(var_os("HOME")?).join(".cache"),
+    };
+
+    Some(base.join("mixel"))
+}
+
+pub fn user_config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Config files read by `Config::load`, in the order they are applied.
+pub fn config_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    files.extend(user_config_path());
+
+    if let Ok(cwd) = current_dir() {
+        files.push(cwd.join(PROJECT_CONFIG));
+    }
+
+    files
+}
+
+pub fn plugin_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("plugins"))
+}
 
 // -----------------------------------------------------------------------------
 //     - Config -
 // -----------------------------------------------------------------------------
+/// Every mode except `Command` has its own key map.
+///
+/// A key sequence that is not bound in the `Insert` or `Visual` key map
+/// falls through to the `Normal` key map, so only the bindings that
+/// differ from normal mode need to be set for those modes.
+/// A binding in the mode's own key map always wins,
+/// even if it is only the start of a longer sequence.
+///
+/// `Command` mode has no key map as all input goes to the command line.
 pub struct Config {
-    actions: HashMap<(Input, Modifiers), Action>,
+    keymaps: HashMap<Mode, KeyMap>,
+    pub timeout: Duration,
+    pub ui: Ui,
+    /// Command name -> commands separated by `;`
+    pub aliases: HashMap<String, String>,
 }
 
 impl Config {
+    /// Load the config in layers, where each layer overrides the previous one:
+    /// 1. Built in defaults
+    /// 2. `$XDG_CONFIG_HOME/mixel/config.toml`
+    /// 3. `.mixel.toml` in the working directory
+    ///
+    /// Missing files are skipped.
+    /// Every problem in every file is returned as `ConfigErrors`.
+    pub fn load() -> Result<Self> {
+        Self::from_files(&config_files())
+    }
+
+    /// Load a single config file on top of the built in defaults.
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
-        let data = read_data(path)?;
-        let cfg = toml::from_slice::<ConfigSrc>(&data)?;
-        let inst = cfg.parse();
+        Self::from_files(&[path.as_ref().to_path_buf()])
+    }
+
+    fn from_files(files: &[PathBuf]) -> Result<Self> {
+        let mut sources = vec![("<default>".to_owned(), DEFAULT_CONFIG.to_owned())];
+        for path in files {
+            if let Ok(text) = read_to_string(path) {
+                sources.push((path.display().to_string(), text));
+            }
+        }
+
+        Self::from_sources(&sources)
+    }
+
+    /// Sources are `(name, toml)`, applied in order.
+    fn from_sources(sources: &[(String, String)]) -> Result<Self> {
+        let figment = sources
+            .iter()
+            .fold(Figment::new(), |figment, (_, text)| figment.merge(Toml::string(text)));
+
+        let cfg = figment.extract::<ConfigSrc>();
+
+        // Validate with the final leader, as it could be set in any of the sources
+        let leader = cfg.as_ref().map(ConfigSrc::leader).unwrap_or_default();
+        let diagnostics = sources
+            .iter()
+            .flat_map(|(name, text)| validate::validate(name, text, &leader))
+            .collect::<Vec<_>>();
+
+        if !diagnostics.is_empty() {
+            return Err(ConfigErrors(diagnostics).into());
+        }
+
+        let inst = cfg?.parse();
         Ok(inst)
     }
 
-    pub fn key_map(&self, input: Input, modifiers: Modifiers) -> Action {
-        *self.actions.get(&(input, modifiers)).unwrap_or(&Action::Noop)
+    pub fn key_map(&self, mode: Mode, keys: &[KeyPress]) -> Match {
+        let lookup = |mode| match self.keymaps.get(&mode) {
+            Some(keymap) => keymap.lookup(keys),
+            None => Match::None,
+        };
+
+        match (mode, lookup(mode)) {
+            (Mode::Insert, Match::None) | (Mode::Visual, Match::None) => lookup(Mode::Normal),
+            (_, found) => found,
+        }
     }
 }
 
@@ -39,77 +184,147 @@ impl Config {
 // -----------------------------------------------------------------------------
 #[derive(Debug, Deserialize)]
 pub struct ConfigSrc {
-    commands: Commands
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default)]
+    commands: Commands,
+    #[serde(default)]
+    ui: Ui,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
 }
 
 impl ConfigSrc {
-    fn parse(self) -> Config {
-        let mut actions = HashMap::new();
-
-        macro_rules! parse {
-            ($field:ident, $action:ident) => {
-                if let Ok(input) = parse_input(&self.commands.$field) {
-                    actions.insert(input, Action::$action);
-                }
-            }
-        }
+    fn leader(&self) -> Vec<KeyPress> {
+        parse_sequence(&self.settings.leader, &[]).unwrap_or_default()
+    }
 
-        parse!(left, Left);
-        parse!(right, Right);
-        parse!(up, Up);
-        parse!(down, Down);
+    fn parse(self) -> Config {
+        let leader = self.leader();
 
-        parse!(up_left, UpLeft);
-        parse!(up_right, UpRight);
-        parse!(down_left, DownLeft);
-        parse!(down_right, DownRight);
+        let mut keymaps = HashMap::new();
+        keymaps.insert(Mode::Normal, self.commands.normal.parse(&leader));
+        keymaps.insert(Mode::Insert, self.commands.insert.parse(&leader));
+        keymaps.insert(Mode::Visual, self.commands.visual.parse(&leader));
 
-        parse!(next_x_pixel, NextXPixel);
-        parse!(prev_x_pixel, PrevXPixel);
-        parse!(next_y_pixel, NextYPixel);
-        parse!(prev_y_pixel, PrevYPixel);
+        Config {
+            keymaps,
+            timeout: Duration::from_millis(self.settings.timeout),
+            ui: self.ui,
+            aliases: self.aliases,
+        }
+    }
+}
 
-        parse!(zoom_in, CanvasZoomIn);
-        parse!(zoom_out, CanvasZoomOut);
+// -----------------------------------------------------------------------------
+//     - Settings -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Key sequence substituted for `<leader>` in bindings
+    leader: String,
+    /// Milliseconds to wait for the rest of a key sequence
+    timeout: u64,
+}
 
-        Config {
-            actions,
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            leader: "\\".to_owned(),
+            timeout: 1000,
         }
     }
 }
 
 // -----------------------------------------------------------------------------
 //     - Commands -
+//     Each mode maps key sequences to action names:
+//     `"<C-w>h" = "left"`, `"<leader>s" = ":w out.png"`
 // -----------------------------------------------------------------------------
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct Commands {
-    left: String,
-    right: String,
-    up: String,
-    down: String,
-
-    up_left: String,
-    up_right: String,
-    down_left: String,
-    down_right: String,
-
-    canvas_left: String,
-    canvas_right: String,
-    canvas_up: String,
-    canvas_down: String,
-
-    zoom_in: String,
-    zoom_out: String,
-
-    next_x_pixel: String,
-    next_y_pixel: String,
-    prev_x_pixel: String,
-    prev_y_pixel: String,
-
-    visual: VisualCommands,
+    normal: Bindings,
+    insert: Bindings,
+    visual: Bindings,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct VisualCommands {
-    fill: String,
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings(HashMap<String, String>);
+
+impl Bindings {
+    fn parse(&self, leader: &[KeyPress]) -> KeyMap {
+        let mut keymap = KeyMap::new();
+
+        for (keys, action) in &self.0 {
+            if let (Ok(keys), Ok(action)) = (parse_sequence(keys, leader), action.parse::<Action>()) {
+                keymap.insert(&keys, action);
+            }
+        }
+
+        keymap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nightmare::events::Modifiers;
+
+    use super::*;
+    use crate::input::Input;
+
+    fn key(c: char) -> Vec<KeyPress> {
+        vec![(Input::Char(c), Modifiers::empty())]
+    }
+
+    fn source(name: &str, text: &str) -> (String, String) {
+        (name.to_owned(), text.to_owned())
+    }
+
+    #[test]
+    fn defaults_load() {
+        let config = Config::from_sources(&[source("default", DEFAULT_CONFIG)]).unwrap();
+        assert!(matches!(config.key_map(Mode::Normal, &key('h')), Match::Exact(Action::Left)));
+    }
+
+    #[test]
+    fn layers_override_defaults() {
+        let user = r#"
+            [commands.normal]
+            h = "noop"
+            q = "left"
+        "#;
+
+        let sources = [source("default", DEFAULT_CONFIG), source("user", user)];
+        let config = Config::from_sources(&sources).unwrap();
+
+        assert!(matches!(config.key_map(Mode::Normal, &key('h')), Match::Exact(Action::Noop)));
+        assert!(matches!(config.key_map(Mode::Normal, &key('q')), Match::Exact(Action::Left)));
+        assert!(matches!(config.key_map(Mode::Normal, &key('l')), Match::Exact(Action::Right)));
+    }
+
+    #[test]
+    fn aliases_are_merged() {
+        let user = "[aliases]\nred = \"colour 255 0 0\"\n";
+        let project = "[aliases]\nblue = \"colour 0 0 255\"\n";
+        let sources = [source("default", DEFAULT_CONFIG), source("user", user), source("project", project)];
+        let config = Config::from_sources(&sources).unwrap();
+
+        assert_eq!(config.aliases.len(), 2);
+        assert_eq!(config.aliases["red"], "colour 255 0 0");
+    }
+
+    #[test]
+    fn invalid_layer_is_reported() {
+        let user = "[commands.normal]\nh = \"lefty\"\n";
+        let sources = [source("default", DEFAULT_CONFIG), source("user", user)];
+        let errors = Config::from_sources(&sources).err().unwrap();
+        let errors = errors.downcast_ref::<ConfigErrors>().unwrap();
+
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].source, "user");
+        assert_eq!(errors.0[0].line, 2);
+    }
 }
@@ -1,117 +1,174 @@
 use nightmare::events::{Key, Modifiers};
 use crate::input::Input;
 
-fn keys_and_mods(keys: impl Iterator<Item=Input>) -> (Vec<Input>, Vec<Modifiers>) {
-    let (keys, mods): (_, Vec<Input>) = keys.partition(|k| match k {
-        Input::Key(Key::LControl) | Input::Key(Key::RControl) => false,
-        Input::Key(Key::LAlt) | Input::Key(Key::RAlt) => false,
-        Input::Key(Key::LShift) | Input::Key(Key::RShift) => false,
-        _ => true,
-    });
-
-    let mods = mods.into_iter().filter_map(|k| match k {
-        Input::Key(Key::LControl) | Input::Key(Key::RControl) => Some(Modifiers::CTRL),
-        Input::Key(Key::LAlt) | Input::Key(Key::RAlt) => Some(Modifiers::ALT),
-        Input::Key(Key::LShift) | Input::Key(Key::RShift) => Some(Modifiers::SHIFT),
-        _ => None
-    }).collect();
-
-    (keys, mods)
+use super::keymap::KeyPress;
+
+// -----------------------------------------------------------------------------
+//     - Key names -
+//     Names used between `<` and `>`, matched without case.
+//     The first name for an input is the one used when formatting.
+// -----------------------------------------------------------------------------
+const KEY_NAMES: &[(&str, Input)] = &[
+    ("Space", Input::Char(' ')),
+    ("lt", Input::Char('<')),
+    ("Bar", Input::Char('|')),
+    ("Tab", Input::Key(Key::Tab)),
+    ("CR", Input::Key(Key::Return)),
+    ("Enter", Input::Key(Key::Return)),
+    ("Return", Input::Key(Key::Return)),
+    ("Esc", Input::Key(Key::Escape)),
+    ("Escape", Input::Key(Key::Escape)),
+    ("BS", Input::Key(Key::Back)),
+    ("Backspace", Input::Key(Key::Back)),
+    ("Del", Input::Key(Key::Delete)),
+    ("Delete", Input::Key(Key::Delete)),
+    ("Insert", Input::Key(Key::Insert)),
+    ("Home", Input::Key(Key::Home)),
+    ("End", Input::Key(Key::End)),
+    ("PageUp", Input::Key(Key::PageUp)),
+    ("PageDown", Input::Key(Key::PageDown)),
+    ("Left", Input::Key(Key::Left)),
+    ("Right", Input::Key(Key::Right)),
+    ("Up", Input::Key(Key::Up)),
+    ("Down", Input::Key(Key::Down)),
+    ("F1", Input::Key(Key::F1)),
+    ("F2", Input::Key(Key::F2)),
+    ("F3", Input::Key(Key::F3)),
+    ("F4", Input::Key(Key::F4)),
+    ("F5", Input::Key(Key::F5)),
+    ("F6", Input::Key(Key::F6)),
+    ("F7", Input::Key(Key::F7)),
+    ("F8", Input::Key(Key::F8)),
+    ("F9", Input::Key(Key::F9)),
+    ("F10", Input::Key(Key::F10)),
+    ("F11", Input::Key(Key::F11)),
+    ("F12", Input::Key(Key::F12)),
+];
+
+fn modifier(c: char) -> Option<Modifiers> {
+    match c.to_ascii_uppercase() {
+        'C' => Some(Modifiers::CTRL),
+        'S' => Some(Modifiers::SHIFT),
+        'A' | 'M' => Some(Modifiers::ALT),
+        _ => None,
+    }
+}
+
+fn str_to_key(s: &str) -> Option<Input> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_control() => return Some(Input::Char(c)),
+        _ => {}
+    }
+
+    KEY_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        .map(|(_, input)| *input)
 }
 
 // -----------------------------------------------------------------------------
 //     - Parse input -
+//     A single key: `a`, `H`, `<C-a>`, `<S-Left>`, `<F5>`, `<C-->`
 // -----------------------------------------------------------------------------
-pub(super) fn parse_input(input: &str) -> Result<(Input, Modifiers), String> {
-    if input.starts_with('<') && input.ends_with('>') {
-        let input = &input[1..input.len() - 1];
-        let keys = input.split('-').filter_map(str_to_key);
-        let (keys, mods) = keys_and_mods(keys);
-        
-        // Modifiers
+pub(super) fn parse_input(input: &str) -> Result<KeyPress, String> {
+    let invalid = || format!("\"{}\" is not a valid key", input);
+
+    if input.len() > 2 && input.starts_with('<') && input.ends_with('>') {
+        let mut inner = &input[1..input.len() - 1];
         let mut modifiers = Modifiers::empty();
-        mods.into_iter().for_each(|m| modifiers.insert(m));
 
-        match (input.contains("--"), keys.into_iter().next()) {
-            (true, _) => Ok((Input::Char('-'), modifiers)),
-            (false, Some(key)) => Ok((key, modifiers)),
-            (false, None) => Err("No key mapping provided".to_owned()),
+        // Modifiers are a single char followed by a dash,
+        // as long as there is a key name left after the dash.
+        while inner.len() > 2 && inner.as_bytes()[1] == b'-' {
+            match inner.chars().next().and_then(modifier) {
+                Some(m) => modifiers.insert(m),
+                None => return Err(invalid()),
+            }
+            inner = &inner[2..];
         }
+
+        let key = str_to_key(inner).ok_or_else(invalid)?;
+        Ok(normalise(key, modifiers))
     } else {
         match str_to_key(input) {
-            Some(k) => Ok((k, Modifiers::empty())),
-            None => Err(format!("\"{}\" is not a valid key", input))
+            Some(Input::Char(c)) if input.len() == c.len_utf8() => Ok(normalise(Input::Char(c), Modifiers::empty())),
+            _ => Err(invalid()),
         }
     }
 }
 
-fn str_to_key(s: &str) -> Option<Input> {
-    match s {
-        // Alpha
-        "a" => Some(Input::Char('a')),
-        "b" => Some(Input::Char('b')),
-        "c" => Some(Input::Char('c')),
-        "d" => Some(Input::Char('d')),
-        "e" => Some(Input::Char('e')),
-        "f" => Some(Input::Char('f')),
-        "g" => Some(Input::Char('g')),
-        "h" => Some(Input::Char('h')),
-        "i" => Some(Input::Char('i')),
-        "j" => Some(Input::Char('j')),
-        "k" => Some(Input::Char('k')),
-        "l" => Some(Input::Char('l')),
-        "m" => Some(Input::Char('m')),
-        "n" => Some(Input::Char('n')),
-        "o" => Some(Input::Char('o')),
-        "p" => Some(Input::Char('p')),
-        "q" => Some(Input::Char('q')),
-        "r" => Some(Input::Char('r')),
-        "s" => Some(Input::Char('s')),
-        "t" => Some(Input::Char('t')),
-        "u" => Some(Input::Char('u')),
-        "v" => Some(Input::Char('v')),
-        "w" => Some(Input::Char('w')),
-        "x" => Some(Input::Char('x')),
-        "y" => Some(Input::Char('y')),
-        "z" => Some(Input::Char('z')),
-
-        // // Numbers
-        // "0" => Some(Input::Char('0')),
-        // "1" => Some(Input::Char('1')),
-        // "1" => Some(Input::Char('1')),
-        // "2" => Some(Input::Char('2')),
-        // "3" => Some(Input::Char('3')),
-        // "4" => Some(Input::Char('4')),
-        // "5" => Some(Input::Char('5')),
-        // "6" => Some(Input::Char('6')),
-        // "7" => Some(Input::Char('7')),
-        // "8" => Some(Input::Char('8')),
-        // "9" => Some(Input::Char('9')),
-
-        // Special chars
-        "'" => Some(Input::Char('\'')),
-        "*" => Some(Input::Char('*')),
-        "@" => Some(Input::Char('@')),
-        ":" => Some(Input::Char(':')),
-        "=" => Some(Input::Char('=')),
-        "-" => Some(Input::Char('-')),
-        "+" => Some(Input::Char('+')),
-        "." => Some(Input::Char('.')),
-        "[" => Some(Input::Char('[')),
-        "]" => Some(Input::Char(']')),
-        ";" => Some(Input::Char(';')),
-        "/" => Some(Input::Char('/')),
-        "_" => Some(Input::Char('_')),
-        "\\" => Some(Input::Char('\\')),
-
-        "Tab" => Some(Input::Key(Key::Tab)),
-
-        // Modifiers
-        "C" => Some(Input::Key(Key::LControl)),
-        "S" => Some(Input::Key(Key::LShift)),
-        "A" => Some(Input::Key(Key::LAlt)),
-        "Left" => Some(Input::Key(Key::Left)),
-        _ => None,
+/// A shifted letter can arrive as `H`, or as `h` with shift held,
+/// so letters are stored in lower case with the shift modifier set for upper case.
+/// For other chars shift is already part of the char (`!` rather than `<S-1>`) and is dropped.
+/// Input is normalised the same way before it is looked up.
+pub fn normalise(input: Input, mut modifiers: Modifiers) -> KeyPress {
+    match input {
+        Input::Char(c) if c.is_alphabetic() => {
+            if c.is_uppercase() {
+                modifiers.insert(Modifiers::SHIFT);
+            }
+            (Input::Char(c.to_ascii_lowercase()), modifiers)
+        }
+        Input::Char(c) if c != ' ' => {
+            modifiers.remove(Modifiers::SHIFT);
+            (Input::Char(c), modifiers)
+        }
+        input => (input, modifiers),
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Format input -
+//     The inverse of `parse_input`
+// -----------------------------------------------------------------------------
+pub fn format_input(key: &KeyPress) -> String {
+    let (input, modifiers) = *key;
+
+    let mut mods = String::new();
+    if modifiers.ctrl() { mods.push_str("C-"); }
+    if modifiers.shift() { mods.push_str("S-"); }
+    if modifiers.alt() { mods.push_str("A-"); }
+
+    let name = KEY_NAMES.iter().find(|(_, i)| *i == input).map(|(name, _)| *name);
+
+    match (input, name) {
+        (_, Some(name)) => format!("<{}{}>", mods, name),
+        (Input::Char(c), None) if mods.is_empty() => c.to_string(),
+        (Input::Char(c), None) if mods == "S-" => c.to_ascii_uppercase().to_string(),
+        (Input::Char(c), None) => format!("<{}{}>", mods, c),
+        (input, None) => format!("<{}{:?}>", mods, input),
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Parse sequence -
+//     A sequence is any number of keys: `gg`, `<C-w>h`, `<leader>ln`
+// -----------------------------------------------------------------------------
+pub(super) fn parse_sequence(input: &str, leader: &[KeyPress]) -> Result<Vec<KeyPress>, String> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+
+    while let Some(c) = rest.chars().next() {
+        let len = match (c, rest.find('>')) {
+            ('<', Some(end)) if end > 1 => end + 1,
+            // A `<` on its own at the end is the key itself
+            ('<', None) if rest.len() > 1 => return Err(format!("\"{}\" is missing a closing `>`", input)),
+            _ => c.len_utf8(),
+        };
+
+        let (token, tail) = rest.split_at(len);
+        rest = tail;
+
+        match token {
+            "<leader>" => keys.extend_from_slice(leader),
+            token => keys.push(parse_input(token)?),
+        }
+    }
+
+    match keys.is_empty() {
+        true => Err("No key mapping provided".to_owned()),
+        false => Ok(keys),
     }
 }
 
@@ -140,6 +197,85 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_parse_sequence() {
+        let keys = parse_sequence("g<C-w>h", &[]).unwrap();
+        assert_eq!(keys.len(), 3);
+        assert!(matches!(keys[0], (Input::Char('g'), _)));
+        assert!(matches!(keys[1], (Input::Char('w'), mods) if mods.ctrl()));
+        assert!(matches!(keys[2], (Input::Char('h'), _)));
+    }
+
+    #[test]
+    fn test_parse_sequence_with_leader() {
+        let leader = parse_sequence("\\", &[]).unwrap();
+        let keys = parse_sequence("<leader>ln", &leader).unwrap();
+        assert_eq!(keys.len(), 3);
+        assert!(matches!(keys[0], (Input::Char('\\'), _)));
+        assert!(matches!(keys[2], (Input::Char('n'), _)));
+    }
+
+    #[test]
+    fn test_invalid_sequence() {
+        assert!(parse_sequence("g<C-", &[]).is_err());
+        assert!(parse_sequence("", &[]).is_err());
+        assert!(matches!(parse_sequence("<C-w><", &[]).unwrap()[1], (Input::Char('<'), _)));
+    }
+
+    #[test]
+    fn test_named_keys() {
+        assert!(matches!(parse_input("<Space>").unwrap(), (Input::Char(' '), _)));
+        assert!(matches!(parse_input("<CR>").unwrap(), (Input::Key(Key::Return), _)));
+        assert!(matches!(parse_input("<esc>").unwrap(), (Input::Key(Key::Escape), _)));
+        assert!(matches!(parse_input("<PageDown>").unwrap(), (Input::Key(Key::PageDown), _)));
+        assert!(matches!(parse_input("<F12>").unwrap(), (Input::Key(Key::F12), _)));
+        assert!(matches!(parse_input("<lt>").unwrap(), (Input::Char('<'), _)));
+    }
+
+    #[test]
+    fn test_digits_and_upper_case() {
+        assert!(matches!(parse_input("0").unwrap(), (Input::Char('0'), _)));
+        assert!(matches!(parse_input("9").unwrap(), (Input::Char('9'), _)));
+        let (input, mods) = parse_input("H").unwrap();
+        assert!(matches!(input, Input::Char('h')));
+        assert!(mods.shift());
+    }
+
+    #[test]
+    fn test_shifted_letter_is_upper_case() {
+        assert_eq!(parse_input("<S-h>").unwrap(), parse_input("H").unwrap());
+        assert_eq!(format_input(&parse_input("<S-h>").unwrap()), "H");
+    }
+
+    #[test]
+    fn test_modifier_with_dash() {
+        let (input, mods) = parse_input("<C-->").unwrap();
+        assert!(matches!(input, Input::Char('-')));
+        assert!(mods.ctrl());
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(parse_input("<Nope>").is_err());
+        assert!(parse_input("<X-a>").is_err());
+        assert!(parse_input("ab").is_err());
+        assert!(parse_input("<>").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let keys = [
+            "a", "Z", "5", "-", "\\", "<Space>", "<lt>", "<Tab>", "<CR>", "<Esc>", "<BS>",
+            "<Del>", "<Home>", "<End>", "<PageUp>", "<Left>", "<Right>", "<Up>", "<Down>",
+            "<F1>", "<C-a>", "<C-S-Left>", "<A-x>", "<C-Space>",
+        ];
+
+        for key in &keys {
+            let parsed = parse_input(key).unwrap();
+            assert_eq!(&format_input(&parsed), key);
+        }
+    }
+
     #[test]
     fn test_directionals() {
         let actual = parse_input("<Left>").unwrap();
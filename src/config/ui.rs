@@ -0,0 +1,201 @@
+use std::convert::TryFrom;
+use std::fs::{create_dir_all, read, remove_file, rename, symlink_metadata, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{anyhow, Context as _, Result};
+use nightmare::pixels::Pixel;
+use serde::Deserialize;
+
+/// Font used when no font is set in the config
+const DEFAULT_FONT: &[u8] = include_bytes!("../../fonts/DejaVuSansMono.ttf");
+const DEFAULT_FONT_NAME: &str = "DejaVuSansMono.ttf";
+
+// -----------------------------------------------------------------------------
+//     - Colour -
+//     `"#rrggbb"` or `"#rrggbbaa"`
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Colour(pub Pixel);
+
+impl TryFrom<String> for Colour {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("\"{}\" is not a valid colour, expected \"#rrggbb\" or \"#rrggbbaa\"", s);
+
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if !(hex.len() == 6 || hex.len() == 8) {
+            return Err(invalid());
+        }
+
+        let channel = |i: usize| match hex.get(i..i + 2) {
+            Some(c) => u8::from_str_radix(c, 16).map_err(|_| invalid()),
+            None => Ok(255),
+        };
+
+        let pixel = Pixel {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: channel(6)?,
+        };
+
+        Ok(Colour(pixel))
+    }
+}
+
+impl From<Colour> for Pixel {
+    fn from(colour: Colour) -> Pixel {
+        colour.0
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Ui -
+// -----------------------------------------------------------------------------
+/// Names of all the fields, used to find unknown settings when validating
+pub(super) const FIELDS: &[&str] = &[
+    "font",
+    "font_size",
+    "clear_colour",
+    "checkerboard",
+    "checkerboard_squares",
+    "padding",
+    "cursor",
+    "grid",
+    "pixel_grid_colour",
+    "pixel_grid_min_scale",
+    "tile_grid",
+    "tile_grid_colour",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Ui {
+    /// Path to a ttf font, the built in DejaVu Sans Mono is used if this isn't set
+    pub font: Option<PathBuf>,
+    pub font_size: f32,
+    /// Colour behind everything
+    pub clear_colour: Colour,
+    /// The two colours of the checkerboard shown through transparent pixels
+    pub checkerboard: [Colour; 2],
+    /// Number of checkerboard squares along each side of the canvas
+    pub checkerboard_squares: usize,
+    /// Space between the window edge and the canvas
+    pub padding: f32,
+    /// Path to an image used as the mouse cursor, a crosshair is drawn if this isn't set
+    pub cursor: Option<PathBuf>,
+    /// Show the grid when starting, `toggle_grid` shows and hides it
+    pub grid: bool,
+    /// Lines between pixels
+    pub pixel_grid_colour: Colour,
+    /// The pixel grid is only shown when zoomed in at least this much
+    pub pixel_grid_min_scale: u32,
+    /// Width and height of a tile in pixels, `[0, 0]` shows no tile grid
+    pub tile_grid: [usize; 2],
+    pub tile_grid_colour: Colour,
+}
+
+impl Ui {
+    /// The font set in the config, or the built in font.
+    /// Text is only loaded from a path, so the built in font
+    /// is written to the user's cache directory the first time it's needed.
+    pub fn font_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.font {
+            return Ok(path.clone());
+        }
+
+        let dir = super::cache_dir().ok_or_else(|| anyhow!("No cache directory for the default font, set `font` in the `[ui]` config"))?;
+        write_default_font(&dir).with_context(|| format!("Failed to write the default font to {}", dir.display()))
+    }
+}
+
+/// Write the built in font to `dir`, unless the same font is already there.
+/// The font is written to a new file and renamed over the old one,
+/// so a symlink in its place is replaced rather than followed.
+fn write_default_font(dir: &Path) -> Result<PathBuf> {
+    let path = dir.join(DEFAULT_FONT_NAME);
+
+    let is_file = symlink_metadata(&path).map(|meta| meta.file_type().is_file()).unwrap_or(false);
+    if is_file && read(&path).map(|bytes| bytes == DEFAULT_FONT).unwrap_or(false) {
+        return Ok(path);
+    }
+
+    create_dir_all(dir)?;
+    let new_path = dir.join(format!("{}.{}", DEFAULT_FONT_NAME, process::id()));
+    drop(remove_file(&new_path));
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&new_path)?;
+    file.write_all(DEFAULT_FONT)?;
+    rename(&new_path, &path)?;
+
+    Ok(path)
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            font: None,
+            font_size: 18.0,
+            // Nypsiee blue
+            clear_colour: Colour(Pixel { r: 12, g: 34, b: 56, a: 255 }),
+            checkerboard: [
+                Colour(Pixel { r: 204, g: 204, b: 204, a: 255 }),
+                Colour(Pixel { r: 153, g: 153, b: 153, a: 255 }),
+            ],
+            checkerboard_squares: 16,
+            padding: 128.0,
+            cursor: None,
+            grid: false,
+            pixel_grid_colour: Colour(Pixel { r: 0, g: 0, b: 0, a: 64 }),
+            pixel_grid_min_scale: 8,
+            tile_grid: [16, 16],
+            tile_grid_colour: Colour(Pixel { r: 255, g: 0, b: 255, a: 160 }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_colour() {
+        let colour = Colour::try_from("#0c2238".to_owned()).unwrap();
+        assert_eq!((colour.0.r, colour.0.g, colour.0.b, colour.0.a), (12, 34, 56, 255));
+
+        let colour = Colour::try_from("#ffffff80".to_owned()).unwrap();
+        assert_eq!(colour.0.a, 128);
+    }
+
+    #[test]
+    fn parse_invalid_colour() {
+        assert!(Colour::try_from("0c2238".to_owned()).is_err());
+        assert!(Colour::try_from("#0c22".to_owned()).is_err());
+        assert!(Colour::try_from("#zz2238".to_owned()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn default_font_replaces_a_symlink() {
+        use std::env::temp_dir;
+        use std::fs::{remove_dir_all, write};
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir().join(format!("mixel-font-test-{}", process::id()));
+        let target = temp_dir().join(format!("mixel-font-target-{}", process::id()));
+        create_dir_all(&dir).unwrap();
+        write(&target, b"not a font").unwrap();
+        symlink(&target, dir.join(DEFAULT_FONT_NAME)).unwrap();
+
+        let path = write_default_font(&dir).unwrap();
+        assert!(symlink_metadata(&path).unwrap().file_type().is_file());
+        assert_eq!(read(&path).unwrap(), DEFAULT_FONT);
+        assert_eq!(read(&target).unwrap(), b"not a font");
+
+        remove_dir_all(dir).unwrap();
+        remove_file(target).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+//! Validate a config file line by line, so every problem
+//! can be reported with the line it's on.
+//!
+//! The file is parsed as a whole first, and each `key = value` line
+//! is then parsed on its own. Values spanning multiple lines are only
+//! checked by the full parse.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use toml::Value;
+
+use super::parse::parse_sequence;
+use super::ui::{Colour, FIELDS as UI_FIELDS};
+use super::{Action, KeyPress};
+use crate::commandline::valid_command_name;
+
+const SECTIONS: &[&str] = &[
+    "settings",
+    "ui",
+    "aliases",
+    "commands",
+    "commands.normal",
+    "commands.insert",
+    "commands.visual",
+];
+const SETTINGS: &[&str] = &["leader", "timeout"];
+
+// -----------------------------------------------------------------------------
+//     - Diagnostic -
+// -----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub source: String,
+    /// One based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.source, self.line, self.message)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Config errors -
+// -----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<Diagnostic>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+// -----------------------------------------------------------------------------
+//     - Validate -
+// -----------------------------------------------------------------------------
+pub(super) fn validate(source: &str, text: &str, leader: &[KeyPress]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut error = |line: usize, message: String| {
+        diagnostics.push(Diagnostic { source: source.to_owned(), line, message })
+    };
+
+    if let Err(e) = toml::from_str::<Value>(text) {
+        let line = e.line_col().map(|(line, _)| line + 1).unwrap_or(1);
+        error(line, e.to_string());
+        return diagnostics;
+    }
+
+    let mut section = String::new();
+    // Parsed key sequence -> (key as written, line)
+    let mut bound: HashMap<Vec<KeyPress>, (String, usize)> = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            let name = line.trim_start_matches('[').split(']').next().unwrap_or("").trim();
+            section = name.to_owned();
+            bound.clear();
+            if !SECTIONS.contains(&name) {
+                error(line_no, format!("unknown section `[{}]`", name));
+            }
+            continue;
+        }
+
+        let (key, value) = match toml::from_str::<toml::value::Table>(line) {
+            Ok(table) if table.len() == 1 => table.into_iter().next().unwrap(),
+            _ => continue,
+        };
+
+        match section.as_str() {
+            "settings" if !SETTINGS.contains(&key.as_str()) => {
+                error(line_no, format!("unknown setting `{}`", key));
+            }
+            "settings" if key == "leader" => {
+                if let Err(e) = value.as_str().map(|s| parse_sequence(s, &[])).unwrap_or(Ok(vec![])) {
+                    error(line_no, format!("invalid leader: {}", e));
+                }
+            }
+            "ui" if !UI_FIELDS.contains(&key.as_str()) => {
+                error(line_no, format!("unknown ui setting `{}`", key));
+            }
+            "ui" => {
+                let colours = match &value {
+                    Value::String(s) => vec![s.clone()],
+                    Value::Array(values) => values.iter().filter_map(Value::as_str).map(str::to_owned).collect(),
+                    _ => vec![],
+                };
+
+                if key.contains("colour") || key == "checkerboard" {
+                    for colour in colours {
+                        if let Err(e) = Colour::try_from(colour) {
+                            error(line_no, e);
+                        }
+                    }
+                }
+            }
+            "aliases" => {
+                if !valid_command_name(&key) {
+                    error(line_no, format!(
+                        "`{}` is not a valid alias name, use letters, digits and `_`, starting with a letter",
+                        key
+                    ));
+                }
+
+                if !value.is_str() {
+                    error(line_no, format!("the commands for `{}` should be a string", key));
+                }
+            }
+            "commands" => {
+                error(line_no, format!(
+                    "`{}` should be in [commands.normal], [commands.insert] or [commands.visual]",
+                    key
+                ));
+            }
+            "commands.normal" | "commands.insert" | "commands.visual" => {
+                let keys = match parse_sequence(&key, leader) {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        error(line_no, format!("invalid key `{}`: {}", key, e));
+                        continue;
+                    }
+                };
+
+                match value.as_str().map(str::parse::<Action>) {
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => error(line_no, format!("unknown action for `{}`: {}", key, e)),
+                    None => error(line_no, format!("the action for `{}` should be a string", key)),
+                }
+
+                match bound.get(&keys) {
+                    Some((other, other_line)) => error(line_no, format!(
+                        "`{}` is the same key as `{}` on line {}",
+                        key, other, other_line
+                    )),
+                    None => drop(bound.insert(keys, (key, line_no))),
+                }
+            }
+            "" => error(line_no, format!("unknown key `{}` outside of a section", key)),
+            _ => {}
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<(usize, String)> {
+        validate("test", text, &[])
+            .into_iter()
+            .map(|d| (d.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let text = "[settings]\ntimeout = 500\n\n[commands.normal]\nh = \"left\"\n\"<C-w>h\" = \":split\"\n";
+        assert!(lines(text).is_empty());
+    }
+
+    #[test]
+    fn invalid_key_and_action() {
+        let text = "[commands.normal]\n\"<Nope>\" = \"left\"\nh = \"lefty\"\n";
+        let errors = lines(text);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, 2);
+        assert_eq!(errors[1].0, 3);
+    }
+
+    #[test]
+    fn duplicate_binding() {
+        let text = "[commands.normal]\nH = \"up\"\n\"<S-h>\" = \"down\"\n";
+        let errors = lines(text);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 3);
+        assert!(errors[0].1.contains("line 2"));
+    }
+
+    #[test]
+    fn same_key_in_different_modes() {
+        let text = "[commands.normal]\nh = \"left\"\n[commands.visual]\nh = \"fill\"\n";
+        assert!(lines(text).is_empty());
+    }
+
+    #[test]
+    fn unknown_section_and_setting() {
+        let text = "[settings]\nleeder = \"x\"\n[colours]\n";
+        let errors = lines(text);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, 2);
+        assert_eq!(errors[1].0, 3);
+    }
+
+    #[test]
+    fn aliases() {
+        let text = "[aliases]\nred = \"colour 255 0 0; alpha 255\"\n2x = \"q\"\nblue = 3\n";
+        let errors = lines(text);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, 3);
+        assert_eq!(errors[1].0, 4);
+    }
+
+    #[test]
+    fn toml_error_has_line() {
+        let errors = lines("[ui]\nfont_size = \n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 2);
+    }
+}
@@ -15,12 +15,10 @@ impl Coords {
         Self(Position::zeros())
     }
 
-    pub fn to_translation(self, height: f32) -> Position {
-        Position::new(self.0.x, height - self.0.y)
-    }
-
-    pub fn from_translation(translation: Position, height: f32) -> Self {
-        Self(Position::new(translation.x, height - translation.y))
+    /// Wrap around the edges of an image,
+    /// so coords on a tiled copy land on the same pixel of the image.
+    pub fn wrap(self, width: f32, height: f32) -> Self {
+        Self::new(self.0.x.rem_euclid(width), self.0.y.rem_euclid(height))
     }
 }
 
@@ -44,3 +42,17 @@ impl Add for Coords {
         Coords::new(self.0.x + rhs.0.x, self.0.y + rhs.0.y)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        let coords = Coords::new(-1.0, 9.0).wrap(8.0, 4.0);
+        assert_eq!((coords.0.x, coords.0.y), (7.0, 1.0));
+
+        let coords = Coords::new(3.0, 2.0).wrap(8.0, 4.0);
+        assert_eq!((coords.0.x, coords.0.y), (3.0, 2.0));
+    }
+}
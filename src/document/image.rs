@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::Result;
+use nightmare::pixels::Pixel;
+
+use super::layer::Layer;
+use super::reference::read_png;
+use super::region::Region;
+
+// -----------------------------------------------------------------------------
+//     - Image -
+//     Layers are drawn bottom to top, so the last layer is on top.
+//     Layer numbers start at one, like in the status bar and commands.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    layers: Vec<Layer>,
+    /// Index of the current layer
+    layer: usize,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: vec![Layer::new(width, height)],
+            layer: 0,
+        }
+    }
+
+    /// Read a png, of any colour type, into an image with one layer
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let (width, height, pixels) = read_png(File::open(path)?)?;
+        let mut image = Self::new(width, height);
+        image.layers[0].set_region(Region::new(0, 0, width, height), &pixels);
+        Ok(image)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Number of the current layer, starting at one
+    pub fn current_layer(&self) -> usize {
+        self.layer + 1
+    }
+
+    pub fn layer(&self) -> &Layer {
+        &self.layers[self.layer]
+    }
+
+    pub fn layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.layer]
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Layer] {
+        &mut self.layers
+    }
+
+    /// Add a layer on top and make it the current layer
+    pub fn new_layer(&mut self) {
+        self.layers.push(Layer::new(self.width, self.height));
+        self.layer = self.layers.len() - 1;
+    }
+
+    /// Remove the current layer.
+    /// The last layer is cleared instead, as there is always at least one.
+    pub fn remove_layer(&mut self) {
+        if self.layers.len() == 1 {
+            self.layer_mut().clear();
+            return;
+        }
+
+        self.layers.remove(self.layer);
+        // Every layer above moved down one
+        self.layers[self.layer..].iter_mut().for_each(Layer::mark_dirty);
+        self.layer = self.layer.min(self.layers.len() - 1);
+    }
+
+    /// Returns false if there is no such layer
+    pub fn set_layer(&mut self, layer: usize) -> bool {
+        match layer {
+            0 => false,
+            n if n > self.layers.len() => false,
+            n => {
+                self.layer = n - 1;
+                true
+            }
+        }
+    }
+
+    /// Show or hide the layers in the range, or the current layer.
+    /// Layers past the last one are ignored.
+    pub fn set_visibility(&mut self, layers: Option<RangeInclusive<usize>>, visible: bool) {
+        let layers = layers.unwrap_or_else(|| self.current_layer()..=self.current_layer());
+
+        self.layers
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| layers.contains(&(index + 1)))
+            .for_each(|(_, layer)| layer.visible = visible);
+    }
+
+    /// Resize every layer, moving the pixels by the offset
+    pub fn resize(&mut self, width: usize, height: usize, offset_x: i32, offset_y: i32) {
+        self.width = width;
+        self.height = height;
+        self.layers
+            .iter_mut()
+            .for_each(|layer| layer.resize(width, height, offset_x, offset_y));
+    }
+
+    /// Rotate every layer a quarter turn clockwise, swapping the width and height
+    pub fn rotate_quarter(&mut self) {
+        std::mem::swap(&mut self.width, &mut self.height);
+        self.layers.iter_mut().for_each(Layer::rotate_quarter);
+    }
+
+    /// The smallest region holding every pixel that isn't transparent, on any layer
+    pub fn content_bounds(&self) -> Option<Region> {
+        let mut bounds: Option<Region> = None;
+
+        for layer in &self.layers {
+            for (i, pixel) in layer.pixels().iter().enumerate() {
+                if pixel.a == 0 {
+                    continue;
+                }
+
+                let pixel = Region::pixel(i % self.width, i / self.width);
+                bounds = Some(bounds.map(|bounds| bounds.union(pixel)).unwrap_or(pixel));
+            }
+        }
+
+        bounds
+    }
+
+    /// The visible layers at one pixel, drawn on top of each other
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Pixel> {
+        self.layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .try_fold(Pixel::transparent(), |dst, layer| Some(blend(dst, layer.pixel(x, y)?)))
+    }
+
+    /// Every visible layer drawn on top of each other
+    pub fn flatten(&self) -> Vec<Pixel> {
+        let mut pixels = vec![Pixel::transparent(); self.width * self.height];
+
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            pixels
+                .iter_mut()
+                .zip(layer.pixels())
+                .for_each(|(dst, src)| *dst = blend(*dst, *src));
+        }
+
+        pixels
+    }
+
+    /// Write the flattened image as a png
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let bytes = self
+            .flatten()
+            .iter()
+            .flat_map(|p| vec![p.r, p.g, p.b, p.a])
+            .collect::<Vec<_>>();
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Draw `src` over `dst`
+fn blend(dst: Pixel, src: Pixel) -> Pixel {
+    match src.a {
+        255 => return src,
+        0 => return dst,
+        _ => {}
+    }
+
+    let src_a = src.a as f32 / 255.0;
+    let dst_a = dst.a as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+
+    let channel = |s: u8, d: u8| {
+        let c = (s as f32 * src_a + d as f32 * dst_a * (1.0 - src_a)) / out_a;
+        c.round() as u8
+    };
+
+    Pixel {
+        r: channel(src.r, dst.r),
+        g: channel(src.g, dst.g),
+        b: channel(src.b, dst.b),
+        a: (out_a * 255.0).round() as u8,
+    }
+}
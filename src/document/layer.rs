@@ -0,0 +1,213 @@
+use nightmare::pixels::Pixel;
+
+use super::region::Region;
+
+// -----------------------------------------------------------------------------
+//     - Layer -
+//     Pixels in rows, top to bottom
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Layer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Pixel>,
+    /// Hidden layers are left out when the image is flattened
+    pub visible: bool,
+    /// The part that changed since the renderer last uploaded it
+    dirty: Option<Region>,
+}
+
+impl Layer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Pixel::transparent(); width * height],
+            visible: true,
+            dirty: Some(Region::new(0, 0, width, height)),
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        Some(y as usize * self.width + x as usize)
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Pixel> {
+        self.index(x, y).map(|i| self.pixels[i])
+    }
+
+    /// Pixels outside of the layer are ignored
+    pub fn set_pixel(&mut self, x: i32, y: i32, pixel: Pixel) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = pixel;
+            self.add_dirty(Region::pixel(x as usize, y as usize));
+        }
+    }
+
+    pub fn fill(&mut self, pixel: Pixel) {
+        self.pixels.iter_mut().for_each(|p| *p = pixel);
+        self.mark_dirty();
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(Pixel::transparent());
+    }
+
+    /// Resize the layer, moving the pixels by the offset.
+    /// Pixels moved outside of the layer are lost and new pixels are transparent.
+    pub fn resize(&mut self, width: usize, height: usize, offset_x: i32, offset_y: i32) {
+        let mut pixels = vec![Pixel::transparent(); width * height];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (new_x, new_y) = (x as i32 + offset_x, y as i32 + offset_y);
+                if new_x < 0 || new_y < 0 || new_x as usize >= width || new_y as usize >= height {
+                    continue;
+                }
+
+                pixels[new_y as usize * width + new_x as usize] = self.pixels[y * self.width + x];
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.pixels = pixels;
+        self.mark_dirty();
+    }
+
+    /// Replace the pixels in the region.
+    /// `source` gets the coords of a pixel inside the region, relative to the region,
+    /// and returns the coords to take its new colour from, or `None` to clear it.
+    pub fn remap(&mut self, region: Region, source: impl Fn(usize, usize) -> Option<(usize, usize)>) {
+        let width = self.width;
+        let index = |x: usize, y: usize| (region.y + y) * width + region.x + x;
+
+        let mut pixels = Vec::with_capacity(region.width * region.height);
+        for y in 0..region.height {
+            for x in 0..region.width {
+                let pixel = match source(x, y) {
+                    Some((x, y)) => self.pixels[index(x, y)],
+                    None => Pixel::transparent(),
+                };
+                pixels.push(pixel);
+            }
+        }
+
+        for (i, pixel) in pixels.into_iter().enumerate() {
+            let i = index(i % region.width, i / region.width);
+            self.pixels[i] = pixel;
+        }
+
+        self.add_dirty(region);
+    }
+
+    /// Rotate the whole layer a quarter turn clockwise, swapping the width and height
+    pub fn rotate_quarter(&mut self) {
+        let (width, height) = (self.height, self.width);
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.pixels[(self.height - 1 - x) * self.width + y]);
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.pixels = pixels;
+        self.mark_dirty();
+    }
+
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// The whole layer needs uploading
+    pub fn mark_dirty(&mut self) {
+        self.dirty = Some(Region::new(0, 0, self.width, self.height));
+    }
+
+    fn add_dirty(&mut self, region: Region) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(region),
+            None => region,
+        });
+    }
+
+    /// The region that changed since the last call, if any
+    pub fn take_dirty(&mut self) -> Option<Region> {
+        self.dirty.take()
+    }
+
+    /// The pixels in the region, row by row
+    pub fn region_pixels(&self, region: Region) -> Vec<Pixel> {
+        let mut pixels = Vec::with_capacity(region.width * region.height);
+
+        for y in region.y..region.y + region.height {
+            let start = y * self.width + region.x;
+            pixels.extend_from_slice(&self.pixels[start..start + region.width]);
+        }
+
+        pixels
+    }
+
+    /// Replace the pixels in the region with pixels from `region_pixels`
+    pub fn set_region(&mut self, region: Region, pixels: &[Pixel]) {
+        for (row, y) in (region.y..region.y + region.height).enumerate() {
+            let start = y * self.width + region.x;
+            let row = &pixels[row * region.width..(row + 1) * region.width];
+            self.pixels[start..start + region.width].copy_from_slice(row);
+        }
+
+        self.add_dirty(region);
+    }
+
+    /// RGBA bytes of the pixels in the region, row by row
+    pub fn bytes(&self, region: Region) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(region.width * region.height * 4);
+
+        for y in region.y..region.y + region.height {
+            let start = y * self.width + region.x;
+            self.pixels[start..start + region.width]
+                .iter()
+                .for_each(|p| bytes.extend_from_slice(&[p.r, p.g, p.b, p.a]));
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dirty_region() {
+        let mut layer = Layer::new(8, 8);
+        assert_eq!(layer.take_dirty(), Some(Region::new(0, 0, 8, 8)));
+        assert_eq!(layer.take_dirty(), None);
+
+        layer.set_pixel(2, 3, Pixel::black());
+        layer.set_pixel(4, 1, Pixel::black());
+        layer.set_pixel(-1, 9, Pixel::black());
+        assert_eq!(layer.take_dirty(), Some(Region::new(2, 1, 3, 3)));
+
+        layer.set_pixel(1, 1, Pixel::black());
+        layer.clear();
+        assert_eq!(layer.take_dirty(), Some(Region::new(0, 0, 8, 8)));
+    }
+
+    #[test]
+    fn region_bytes() {
+        let mut layer = Layer::new(3, 3);
+        layer.set_pixel(1, 1, Pixel { r: 1, g: 2, b: 3, a: 4 });
+
+        assert_eq!(layer.bytes(Region::pixel(1, 1)), vec![1, 2, 3, 4]);
+        assert_eq!(layer.bytes(Region::new(1, 0, 2, 2)).len(), 16);
+        assert_eq!(&layer.bytes(Region::new(0, 0, 3, 3))[16..20], &[1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,813 @@
+//! The images being edited, without anything to draw them with.
+//!
+//! Commands are applied to the document the same way
+//! with or without a window, so everything here can be
+//! used headless and tested without a GPU.
+use anyhow::{anyhow, bail, Context as _, Result};
+use nightmare::pixels::Pixel;
+use nightmare::Position;
+
+use crate::commandline::{Command, Direction, RefCommand, Target};
+
+mod image;
+mod layer;
+mod reference;
+mod region;
+mod transform;
+
+/// Undo steps kept per document, the oldest are dropped first
+const MAX_UNDO: usize = 100;
+
+pub use image::Image;
+pub use layer::Layer;
+pub use reference::{read_png, Reference};
+pub use region::Region;
+
+// -----------------------------------------------------------------------------
+//     - Document -
+// -----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Document {
+    images: Vec<Image>,
+    /// Index of the current image
+    image: Option<usize>,
+    /// Colour used by `:put`
+    pub colour: Pixel,
+    /// Set while selecting in visual mode, `:crop` crops to it
+    pub selection: Option<Region>,
+    /// Shown with the current image, but never edited or saved
+    pub reference: Option<Reference>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Changes made since `begin_stroke`, undone as one
+    stroke: Option<Snapshot>,
+}
+
+/// Changes to an image, undone last to first
+#[derive(Debug)]
+struct Snapshot {
+    index: usize,
+    changes: Vec<Change>,
+}
+
+/// What a change replaced
+#[derive(Debug)]
+enum Change {
+    /// The whole image, for changes to the size or the layers
+    Image(Image),
+    /// The pixels in a region of a layer, for drawing
+    Pixels { layer: usize, region: Region, pixels: Vec<Pixel> },
+}
+
+impl Change {
+    /// Put back what the change replaced.
+    /// Returns what is replaced in turn, to undo the undo.
+    fn restore(self, image: &mut Image) -> Change {
+        match self {
+            Change::Image(mut replaced) => {
+                replaced.layers_mut().iter_mut().for_each(Layer::mark_dirty);
+                std::mem::swap(image, &mut replaced);
+                Change::Image(replaced)
+            }
+            Change::Pixels { layer, region, pixels } => {
+                let target = &mut image.layers_mut()[layer];
+                let replaced = target.region_pixels(region);
+                target.set_region(region, &pixels);
+                Change::Pixels { layer, region, pixels: replaced }
+            }
+        }
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            images: Vec::new(),
+            image: None,
+            colour: Pixel::black(),
+            selection: None,
+            reference: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            stroke: None,
+        }
+    }
+
+    pub fn image(&self) -> Option<&Image> {
+        self.image.map(|index| &self.images[index])
+    }
+
+    /// Index of the current image
+    pub fn current(&self) -> Option<usize> {
+        self.image
+    }
+
+    pub fn image_at(&self, index: usize) -> Option<&Image> {
+        self.images.get(index)
+    }
+
+    pub fn images_mut(&mut self) -> &mut [Image] {
+        &mut self.images
+    }
+
+    /// Make the image at `index` the one commands apply to.
+    /// Returns false if there is no such image.
+    pub fn select_image(&mut self, index: usize) -> bool {
+        match index < self.images.len() {
+            true => {
+                self.image = Some(index);
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn image_mut(&mut self) -> Result<&mut Image> {
+        match self.image {
+            Some(index) => Ok(&mut self.images[index]),
+            None => Err(anyhow!("there is no image, create one with `:new <width> <height>`")),
+        }
+    }
+
+    /// Apply a command that changes the document.
+    /// Any other command is ignored.
+    ///
+    /// Commands that change the pixels or the size of an image
+    /// can be undone, one command at a time or one stroke at a time.
+    pub fn apply(&mut self, command: &Command) -> Result<()> {
+        let change = match undoable(command) {
+            true => self.change(command)?,
+            false => None,
+        };
+
+        self.edit(command)?;
+
+        if let Some(change) = change {
+            self.record(change);
+        }
+
+        Ok(())
+    }
+
+    /// Every change until `end_stroke` is undone as one,
+    /// like all the pixels drawn while the mouse button is held.
+    pub fn begin_stroke(&mut self) {
+        self.end_stroke();
+        let index = self.image.unwrap_or_default();
+        self.stroke = Some(Snapshot { index, changes: Vec::new() });
+    }
+
+    pub fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            if !stroke.changes.is_empty() {
+                self.push_undo(stroke);
+            }
+        }
+    }
+
+    /// What the command is about to replace.
+    /// Drawing only keeps the pixels it draws over, anything else keeps the whole image.
+    fn change(&mut self, command: &Command) -> Result<Option<Change>> {
+        let image = self.image_mut()?;
+        let whole = Region::new(0, 0, image.width(), image.height());
+
+        let region = match command {
+            Command::Put(target) | Command::Clear(target) => match target {
+                Target::Layer => Some(whole),
+                Target::Pixel(pos) => corners(*pos, *pos).intersect(whole),
+                Target::Rect(from, to) => corners(*from, *to).intersect(whole),
+            },
+            _ => return Ok(Some(Change::Image(image.clone()))),
+        };
+
+        let layer = image.current_layer();
+        let change = region.map(|region| Change::Pixels {
+            layer,
+            region,
+            pixels: image.layer().region_pixels(region),
+        });
+
+        Ok(change)
+    }
+
+    fn record(&mut self, change: Change) {
+        let index = self.image.unwrap_or_default();
+        self.redo.clear();
+
+        match &mut self.stroke {
+            Some(stroke) if stroke.index == index => stroke.changes.push(change),
+            _ => self.push_undo(Snapshot { index, changes: vec![change] }),
+        }
+    }
+
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(snapshot);
+    }
+
+    /// Put back everything the snapshot replaced, last change first.
+    /// Returns what was replaced, to go on the other stack.
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let Snapshot { index, changes } = snapshot;
+        let image = &mut self.images[index];
+        let changes = changes.into_iter().rev().map(|change| change.restore(image)).collect();
+        self.image = Some(index);
+        self.selection = None;
+        Snapshot { index, changes }
+    }
+
+    fn edit(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::NewImage(size) => {
+                if size.x < 1.0 || size.y < 1.0 {
+                    bail!("an image needs to be at least 1x1");
+                }
+
+                self.images.push(Image::new(size.x as usize, size.y as usize));
+                self.image = Some(self.images.len() - 1);
+            }
+            Command::Edit(path) => {
+                let image = Image::load(path).with_context(|| format!("can't load \"{}\"", path))?;
+                self.images.push(image);
+                self.image = Some(self.images.len() - 1);
+            }
+            Command::ShowImage(image) => {
+                if !self.select_image(image - 1) {
+                    bail!("there is no image {}, the document has {}", image, self.images.len());
+                }
+            }
+            Command::SetColour(colour) => self.colour = *colour,
+            Command::SetAlpha(alpha) => self.colour.a = *alpha,
+            Command::Put(target) => {
+                let colour = self.colour;
+                paint(self.image_mut()?, target, colour);
+            }
+            Command::Clear(target) => paint(self.image_mut()?, target, Pixel::transparent()),
+            Command::NewLayer => self.image_mut()?.new_layer(),
+            Command::RemoveLayer => self.image_mut()?.remove_layer(),
+            Command::ChangeLayer(layer) => {
+                let image = self.image_mut()?;
+                if !image.set_layer(*layer) {
+                    bail!("there is no layer {}, the image has {}", layer, image.layer_count());
+                }
+            }
+            Command::LayerVisibility { layers, visible } => {
+                self.image_mut()?.set_visibility(layers.clone(), *visible);
+            }
+            Command::Save { path, overwrite } => {
+                if !overwrite && std::path::Path::new(path).exists() {
+                    bail!("\"{}\" already exists, use `:w!` to overwrite it", path);
+                }
+
+                self.image_mut()?.save(path)?;
+            }
+            Command::Batch(commands) => {
+                // Undone as one, unless it's already part of a stroke
+                let stroke = self.stroke.is_none();
+                if stroke {
+                    self.begin_stroke();
+                }
+
+                let result = commands.iter().try_for_each(|command| self.apply(command));
+
+                if stroke {
+                    self.end_stroke();
+                }
+
+                result?;
+            }
+            Command::Resize { width, height, anchor } => {
+                let image = self.image_mut()?;
+                let (x, y) = anchor.halves();
+                let offset_x = (*width as i32 - image.width() as i32) * x / 2;
+                let offset_y = (*height as i32 - image.height() as i32) * y / 2;
+                resize(image, *width as i32, *height as i32, offset_x, offset_y)?;
+            }
+            Command::Extend { direction, amount } => {
+                let image = self.image_mut()?;
+                let (width, height) = (image.width() as i32, image.height() as i32);
+                let amount = *amount;
+                match direction {
+                    Direction::Left => resize(image, width + amount, height, amount, 0)?,
+                    Direction::Right => resize(image, width + amount, height, 0, 0)?,
+                    Direction::Up => resize(image, width, height + amount, 0, amount)?,
+                    Direction::Down => resize(image, width, height + amount, 0, 0)?,
+                }
+            }
+            Command::Crop => {
+                let selection = self.selection.take();
+                let image = self.image_mut()?;
+                let whole = Region::new(0, 0, image.width(), image.height());
+
+                let region = match selection {
+                    Some(selection) => selection.intersect(whole).ok_or_else(|| anyhow!("the selection is outside of the image"))?,
+                    None => image.content_bounds().ok_or_else(|| anyhow!("nothing to crop to, the image is empty"))?,
+                };
+
+                image.resize(region.width, region.height, -(region.x as i32), -(region.y as i32));
+            }
+            Command::Transform { transform, all_layers } => {
+                let selection = self.selection;
+                transform::apply(self.image_mut()?, *transform, selection, *all_layers)?;
+            }
+            Command::Pick(pos) => self.pick(pos.x as i32, pos.y as i32)?,
+            Command::Reference(command) => self.reference(command)?,
+            Command::Undo => {
+                self.end_stroke();
+                let snapshot = self.undo.pop().ok_or_else(|| anyhow!("nothing to undo"))?;
+                let replaced = self.restore(snapshot);
+                self.redo.push(replaced);
+            }
+            Command::Redo => {
+                self.end_stroke();
+                let snapshot = self.redo.pop().ok_or_else(|| anyhow!("nothing to redo"))?;
+                let replaced = self.restore(snapshot);
+                self.undo.push(replaced);
+            }
+            Command::Noop
+            | Command::Quit
+            | Command::Split(_)
+            | Command::CloseSelectedSplit
+            | Command::GrowSplit { .. }
+            | Command::EqualiseSplits
+            | Command::MaximiseSplit
+            | Command::Lua(_)
+            | Command::Log(_)
+            | Command::Define { .. }
+            | Command::Source(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Document {
+    /// Take the colour from the image, or from the reference where the image is transparent.
+    /// With the reference above the image it's the other way round.
+    fn pick(&mut self, x: i32, y: i32) -> Result<()> {
+        let image = self.image_mut()?.pixel(x, y);
+        let reference = self.reference.as_ref().and_then(|reference| reference.pixel_at(x, y));
+        let above = self.reference.as_ref().map(|reference| reference.above).unwrap_or(false);
+
+        let opaque = |pixel: Option<Pixel>| pixel.filter(|pixel| pixel.a > 0);
+        let picked = match above {
+            true => opaque(reference).or_else(|| opaque(image)),
+            false => opaque(image).or_else(|| opaque(reference)),
+        };
+
+        self.colour = match (picked, image) {
+            (Some(pixel), _) => pixel,
+            (None, Some(_)) => Pixel::transparent(),
+            (None, None) => bail!("{},{} is outside of the image", x, y),
+        };
+
+        Ok(())
+    }
+
+    fn reference(&mut self, command: &RefCommand) -> Result<()> {
+        let reference = match command {
+            RefCommand::Load(path) => {
+                let reference = Reference::load(path).with_context(|| format!("can't load \"{}\"", path.display()))?;
+                self.reference = Some(reference);
+                return Ok(());
+            }
+            RefCommand::Clear => {
+                self.reference = None;
+                return Ok(());
+            }
+            _ => self.reference.as_mut().ok_or_else(|| anyhow!("there is no reference image, load one with `:ref <path>`"))?,
+        };
+
+        match command {
+            RefCommand::Opacity(opacity) => {
+                reference.opacity = *opacity;
+                reference.mark_dirty();
+            }
+            RefCommand::Offset(x, y) => reference.offset = (*x, *y),
+            RefCommand::Scale(scale) => reference.scale = *scale,
+            RefCommand::Above => reference.above = true,
+            RefCommand::Below => reference.above = false,
+            RefCommand::Load(_) | RefCommand::Clear => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn undoable(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Put(_)
+            | Command::Clear(_)
+            | Command::NewLayer
+            | Command::RemoveLayer
+            | Command::Resize { .. }
+            | Command::Extend { .. }
+            | Command::Crop
+            | Command::Transform { .. }
+    )
+}
+
+/// The region between two corners, both included.
+/// Negative positions are clamped to zero, as nothing is drawn there.
+fn corners(from: Position, to: Position) -> Region {
+    let (min_x, max_x) = (from.x.min(to.x).max(0.0) as usize, from.x.max(to.x).max(0.0) as usize);
+    let (min_y, max_y) = (from.y.min(to.y).max(0.0) as usize, from.y.max(to.y).max(0.0) as usize);
+    Region::new(min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)
+}
+
+fn resize(image: &mut Image, width: i32, height: i32, offset_x: i32, offset_y: i32) -> Result<()> {
+    if width < 1 || height < 1 {
+        bail!("an image needs to be at least 1x1");
+    }
+
+    image.resize(width as usize, height as usize, offset_x, offset_y);
+    Ok(())
+}
+
+/// Set every pixel of the target on the current layer
+fn paint(image: &mut Image, target: &Target, pixel: Pixel) {
+    let layer = image.layer_mut();
+
+    let (from, to) = match target {
+        Target::Layer => return layer.fill(pixel),
+        Target::Pixel(pos) => (*pos, *pos),
+        Target::Rect(from, to) => (*from, *to),
+    };
+
+    let (min_x, max_x) = (from.x.min(to.x) as i32, from.x.max(to.x) as i32);
+    let (min_y, max_y) = (from.y.min(to.y) as i32, from.y.max(to.y) as i32);
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            layer.set_pixel(x, y, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commandline::Parser;
+
+    fn rgba(pixel: Pixel) -> (u8, u8, u8, u8) {
+        (pixel.r, pixel.g, pixel.b, pixel.a)
+    }
+
+    fn run(document: &mut Document, src: &str) {
+        let command = Parser::new(src).parse().unwrap();
+        document.apply(&command).unwrap();
+    }
+
+    fn document(commands: &[&str]) -> Document {
+        let mut document = Document::new();
+        commands.iter().for_each(|src| run(&mut document, src));
+        document
+    }
+
+    #[test]
+    fn put_rect() {
+        let document = document(&[":new 4 4", ":colour 255 0 0", ":put 1,1 2,2"]);
+        let layer = document.image().unwrap().layer();
+
+        assert_eq!(rgba(layer.pixel(1, 1).unwrap()), (255, 0, 0, 255));
+        assert_eq!(rgba(layer.pixel(2, 2).unwrap()), (255, 0, 0, 255));
+        assert_eq!(rgba(layer.pixel(3, 3).unwrap()).3, 0);
+        assert_eq!(layer.pixels().iter().filter(|p| p.a == 255).count(), 4);
+    }
+
+    #[test]
+    fn clear_layer() {
+        let document = document(&[":new 2 2", ":put *", ":clear 0,0", ":newlayer", ":put *", ":clear *"]);
+        let image = document.image().unwrap();
+
+        assert_eq!(image.layer_count(), 2);
+        assert!(image.layer().pixels().iter().all(|p| p.a == 0));
+        assert_eq!(image.layers()[0].pixels().iter().filter(|p| p.a == 255).count(), 3);
+    }
+
+    #[test]
+    fn flatten_visible_layers() {
+        let document = document(&[
+            ":new 1 1",
+            ":colour 255 0 0",
+            ":put 0,0",
+            ":newlayer",
+            ":colour 0 0 255",
+            ":put 0,0",
+        ]);
+        assert_eq!(rgba(document.image().unwrap().flatten()[0]), (0, 0, 255, 255));
+
+        let mut document = document;
+        run(&mut document, ":2layer hide");
+        assert_eq!(rgba(document.image().unwrap().flatten()[0]), (255, 0, 0, 255));
+    }
+
+    #[test]
+    fn blend_half_alpha() {
+        let document = document(&[
+            ":new 1 1",
+            ":colour 0 0 0",
+            ":put 0,0",
+            ":newlayer",
+            ":colour 255 255 255",
+            ":alpha 128",
+            ":put 0,0",
+        ]);
+        let (r, _, _, a) = rgba(document.image().unwrap().flatten()[0]);
+
+        assert_eq!(a, 255);
+        assert_eq!(r, 128);
+    }
+
+    #[test]
+    fn dirty_layers() {
+        let mut document = document(&[":new 2 2", ":newlayer", ":newlayer"]);
+        let image = &mut document.images_mut()[0];
+        assert!(image.layers_mut().iter_mut().all(|layer| layer.take_dirty().is_some()));
+        assert!(image.layers_mut().iter_mut().all(|layer| layer.take_dirty().is_none()));
+
+        run(&mut document, ":put 0,0");
+        let dirty = |document: &mut Document| {
+            document.images_mut()[0].layers_mut().iter_mut().map(|layer| layer.take_dirty().is_some()).collect::<Vec<_>>()
+        };
+        assert_eq!(dirty(&mut document), vec![false, false, true]);
+
+        // Removing a layer moves the layers above it down
+        run(&mut document, ":layer 1");
+        run(&mut document, ":removelayer");
+        assert_eq!(dirty(&mut document), vec![true, true]);
+
+        run(&mut document, ":layer hide");
+        assert_eq!(dirty(&mut document), vec![false, false]);
+    }
+
+    #[test]
+    fn show_image() {
+        let mut document = document(&[":new 2 2", ":new 3 3", ":image 1"]);
+        assert_eq!(document.current(), Some(0));
+
+        let error = document.apply(&Parser::new(":image 3").parse().unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "there is no image 3, the document has 2");
+    }
+
+    fn size(document: &Document) -> (usize, usize) {
+        let image = document.image().unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn resize_with_anchor() {
+        let mut document = document(&[":new 4 4", ":put 0,0", ":resize 6 2 bottomright"]);
+        assert_eq!(size(&document), (6, 2));
+        assert!(document.image().unwrap().layer().pixels().iter().all(|p| p.a == 0));
+
+        run(&mut document, ":resize 10 6 centre");
+        assert_eq!(size(&document), (10, 6));
+
+        let mut document = self::document(&[":new 4 4", ":put 3,3", ":resize 8 8 centre"]);
+        assert_eq!(document.image().unwrap().layer().pixel(5, 5).unwrap().a, 255);
+        assert!(document.apply(&Parser::new(":resize 0 8").parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn extend() {
+        let mut document = document(&[":new 2 2", ":put 0,0", ":extend left 3", ":extend down 1"]);
+        assert_eq!(size(&document), (5, 3));
+        assert_eq!(document.image().unwrap().layer().pixel(3, 0).unwrap().a, 255);
+
+        run(&mut document, ":extend up -1");
+        assert_eq!(size(&document), (5, 2));
+        assert_eq!(document.image().unwrap().layer().pixel(3, 0).unwrap().a, 0);
+        assert!(document.apply(&Parser::new(":extend right -5").parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn crop() {
+        let mut document = document(&[":new 8 8", ":put 2,3", ":newlayer", ":put 5,4", ":crop"]);
+        assert_eq!(size(&document), (4, 2));
+        assert_eq!(document.image().unwrap().layers()[0].pixel(0, 0).unwrap().a, 255);
+        assert_eq!(document.image().unwrap().layer().pixel(3, 1).unwrap().a, 255);
+
+        document.selection = Some(Region::new(3, 1, 5, 5));
+        run(&mut document, ":crop");
+        assert_eq!(size(&document), (1, 1));
+        assert!(document.selection.is_none());
+
+        let mut empty = self::document(&[":new 2 2"]);
+        assert!(empty.apply(&Command::Crop).is_err());
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut document = document(&[":new 4 4", ":put 1,1", ":resize 2 2"]);
+        run(&mut document, ":undo");
+        assert_eq!(size(&document), (4, 4));
+        run(&mut document, ":undo");
+        assert!(document.image().unwrap().layer().pixels().iter().all(|p| p.a == 0));
+        assert!(document.apply(&Command::Undo).is_err());
+
+        run(&mut document, ":redo");
+        assert_eq!(document.image().unwrap().layer().pixel(1, 1).unwrap().a, 255);
+        run(&mut document, ":redo");
+        assert_eq!(size(&document), (2, 2));
+        assert!(document.apply(&Command::Redo).is_err());
+
+        // A new change can't be redone past
+        run(&mut document, ":undo");
+        run(&mut document, ":put 0,0");
+        assert!(document.apply(&Command::Redo).is_err());
+    }
+
+    #[test]
+    fn undo_keeps_only_the_pixels_drawn_over() {
+        let mut document = document(&[":new 64 64", ":put 1,1", ":put 2,2 3,4"]);
+        let regions = document.undo.iter().map(|snapshot| match &snapshot.changes[..] {
+            [Change::Pixels { region, pixels, .. }] => (*region, pixels.len()),
+            other => panic!("{:?}", other),
+        });
+        assert_eq!(regions.collect::<Vec<_>>(), vec![(Region::pixel(1, 1), 1), (Region::new(2, 2, 2, 3), 6)]);
+
+        // Nothing outside of the image to undo
+        run(&mut document, ":put 100,100");
+        assert_eq!(document.undo.len(), 2);
+    }
+
+    #[test]
+    fn undo_stroke() {
+        let mut document = document(&[":new 4 4", ":put 0,0"]);
+        document.begin_stroke();
+        run(&mut document, ":put 1,0");
+        run(&mut document, ":put 2,0");
+        run(&mut document, ":clear 0,0");
+        document.end_stroke();
+
+        let alpha = |document: &Document| (0..3).map(|x| document.image().unwrap().layer().pixel(x, 0).unwrap().a).collect::<Vec<_>>();
+        assert_eq!(alpha(&document), vec![0, 255, 255]);
+
+        run(&mut document, ":undo");
+        assert_eq!(alpha(&document), vec![255, 0, 0]);
+        run(&mut document, ":redo");
+        assert_eq!(alpha(&document), vec![0, 255, 255]);
+
+        // An alias is undone as one
+        let mut aliases = std::collections::HashMap::new();
+        aliases.insert("dots".to_owned(), "put 3,3; put 3,2".to_owned());
+        document.apply(&Parser::new(":dots").aliases(&aliases).parse().unwrap()).unwrap();
+        run(&mut document, ":undo");
+        assert_eq!(document.image().unwrap().layer().pixel(3, 2).unwrap().a, 0);
+        assert_eq!(document.image().unwrap().layer().pixel(3, 3).unwrap().a, 0);
+    }
+
+    fn row(document: &Document, layer: usize, y: i32) -> Vec<u8> {
+        let layer = &document.image().unwrap().layers()[layer];
+        (0..layer_width(layer)).map(|x| layer.pixel(x, y).unwrap().r).collect()
+    }
+
+    fn layer_width(layer: &Layer) -> i32 {
+        (0..).take_while(|x| layer.pixel(*x, 0).is_some()).count() as i32
+    }
+
+    /// A 3x2 image with red 10, 20, 30 on the top row
+    fn strip() -> Document {
+        document(&[":new 3 2", ":colour 10 0 0", ":put 0,0", ":colour 20 0 0", ":put 1,0", ":colour 30 0 0", ":put 2,0"])
+    }
+
+    #[test]
+    fn flip() {
+        let mut document = strip();
+        run(&mut document, ":flip h");
+        assert_eq!(row(&document, 0, 0), vec![30, 20, 10]);
+
+        run(&mut document, ":flip v");
+        assert_eq!(row(&document, 0, 0), vec![0, 0, 0]);
+        assert_eq!(row(&document, 0, 1), vec![30, 20, 10]);
+    }
+
+    #[test]
+    fn rotate() {
+        let mut document = strip();
+        assert!(document.apply(&Parser::new(":rotate 90").parse().unwrap()).is_err());
+
+        run(&mut document, ":rotate 90 all");
+        assert_eq!(size(&document), (2, 3));
+        assert_eq!(row(&document, 0, 0), vec![0, 10]);
+        assert_eq!(row(&document, 0, 2), vec![0, 30]);
+
+        run(&mut document, ":rotate 180");
+        assert_eq!(row(&document, 0, 0), vec![30, 0]);
+
+        run(&mut document, ":rotate 270 all");
+        assert_eq!(size(&document), (3, 2));
+        assert_eq!(row(&document, 0, 0), vec![0, 0, 0]);
+        assert_eq!(row(&document, 0, 1), vec![30, 20, 10]);
+    }
+
+    #[test]
+    fn shift() {
+        let mut document = strip();
+        run(&mut document, ":shift 1 0 wrap");
+        assert_eq!(row(&document, 0, 0), vec![30, 10, 20]);
+
+        run(&mut document, ":shift -1 1");
+        assert_eq!(row(&document, 0, 0), vec![0, 0, 0]);
+        assert_eq!(row(&document, 0, 1), vec![10, 20, 0]);
+    }
+
+    #[test]
+    fn transform_selection_and_layers() {
+        let mut document = strip();
+        run(&mut document, ":newlayer");
+        run(&mut document, ":put 0,0");
+
+        document.selection = Some(Region::new(0, 0, 2, 1));
+        run(&mut document, ":flip h");
+        assert_eq!(row(&document, 0, 0), vec![10, 20, 30]);
+        assert_eq!(document.image().unwrap().layer().pixel(1, 0).unwrap().a, 255);
+
+        run(&mut document, ":flip h all");
+        assert_eq!(row(&document, 0, 0), vec![20, 10, 30]);
+
+        document.selection = None;
+        run(&mut document, ":undo");
+        run(&mut document, ":undo");
+        assert_eq!(document.image().unwrap().layer().pixel(0, 0).unwrap().a, 255);
+    }
+
+    #[test]
+    fn pick() {
+        let mut document = document(&[":new 4 4", ":colour 10 20 30", ":put 1,1", ":colour 0 0 0"]);
+        run(&mut document, ":pick 1,1");
+        assert_eq!(rgba(document.colour), (10, 20, 30, 255));
+
+        run(&mut document, ":pick 0,0");
+        assert_eq!(rgba(document.colour).3, 0);
+        assert!(document.apply(&Parser::new(":pick 9,9").parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn pick_from_reference() {
+        let red = Pixel { r: 255, g: 0, b: 0, a: 255 };
+        let mut document = document(&[":new 4 4", ":colour 0 0 255", ":put 0,0"]);
+        document.reference = Some(Reference::new(2, 2, vec![red; 4]));
+        run(&mut document, ":ref scale 2");
+
+        run(&mut document, ":pick 3,3");
+        assert_eq!(rgba(document.colour), (255, 0, 0, 255));
+
+        // The image wins unless the reference is above it
+        run(&mut document, ":pick 0,0");
+        assert_eq!(rgba(document.colour), (0, 0, 255, 255));
+        run(&mut document, ":ref above");
+        run(&mut document, ":pick 0,0");
+        assert_eq!(rgba(document.colour), (255, 0, 0, 255));
+
+        run(&mut document, ":ref offset 2 2");
+        run(&mut document, ":pick 1,1");
+        assert_eq!(rgba(document.colour).3, 0);
+
+        run(&mut document, ":ref clear");
+        assert!(document.apply(&Parser::new(":ref below").parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn load_reference() {
+        let path = std::env::temp_dir().join("mixel-reference-test.png");
+        document(&[":new 3 2", ":colour 1 2 3", ":put 2,1", &format!(":w! {}", path.display())]);
+
+        let mut document = document(&[":new 8 8", &format!(":ref {}", path.display())]);
+        let reference = document.reference.as_ref().unwrap();
+        assert_eq!((reference.width(), reference.height()), (3, 2));
+        assert_eq!(rgba(reference.pixel_at(2, 1).unwrap()), (1, 2, 3, 255));
+
+        run(&mut document, ":ref clear");
+        assert!(document.reference.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edit() {
+        let path = std::env::temp_dir().join("mixel-edit-test.png");
+        document(&[":new 3 2", ":colour 1 2 3", ":put 2,1", &format!(":w! {}", path.display())]);
+
+        let mut document = document(&[":new 8 8", &format!(":e {}", path.display())]);
+        assert_eq!(document.images.len(), 2);
+        let image = document.image_mut().unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(rgba(image.pixel(2, 1).unwrap()), (1, 2, 3, 255));
+
+        assert!(document.apply(&Parser::new(":edit missing.png").parse().unwrap()).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let mut document = Document::new();
+        assert!(document.apply(&Command::NewLayer).is_err());
+
+        run(&mut document, ":new 2 2");
+        assert!(document.apply(&Parser::new(":layer 3").parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use nightmare::pixels::Pixel;
+
+// -----------------------------------------------------------------------------
+//     - Reference -
+//     An image shown with the one being edited, to trace over or pick colours from.
+//     It's never drawn on and never saved.
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Reference {
+    width: usize,
+    height: usize,
+    pixels: Vec<Pixel>,
+    pub opacity: u8,
+    /// Position of the top left pixel, in image pixels
+    pub offset: (i32, i32),
+    /// Image pixels per reference pixel
+    pub scale: f32,
+    /// Shown above the layers rather than below them
+    pub above: bool,
+    /// Changed since the renderer last uploaded it
+    dirty: bool,
+}
+
+impl Reference {
+    pub fn new(width: usize, height: usize, pixels: Vec<Pixel>) -> Self {
+        Self {
+            width,
+            height,
+            pixels,
+            opacity: 128,
+            offset: (0, 0),
+            scale: 1.0,
+            above: false,
+            dirty: true,
+        }
+    }
+
+    /// Read a png, of any colour type
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let (width, height, pixels) = read_png(File::open(path)?)?;
+        Ok(Self::new(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The reference pixel shown over the image pixel at `x`, `y`
+    pub fn pixel_at(&self, x: i32, y: i32) -> Option<Pixel> {
+        let x = ((x - self.offset.0) as f32 / self.scale).floor();
+        let y = ((y - self.offset.1) as f32 / self.scale).floor();
+
+        if x < 0.0 || y < 0.0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        Some(self.pixels[y as usize * self.width + x as usize])
+    }
+
+    /// RGBA bytes with the opacity applied, row by row
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for p in &self.pixels {
+            let a = (p.a as u32 * self.opacity as u32 / 255) as u8;
+            bytes.extend_from_slice(&[p.r, p.g, p.b, a]);
+        }
+
+        bytes
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns true if the pixels or the opacity changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+/// Width, height and pixels of a png, of any colour type
+pub fn read_png(reader: impl Read) -> Result<(usize, usize, Vec<Pixel>)> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder.read_info()?;
+    let mut bytes = vec![0; info.buffer_size()];
+    reader.next_frame(&mut bytes)?;
+
+    let pixels = match reader.output_color_type().0 {
+        png::ColorType::RGBA => bytes.chunks(4).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect(),
+        png::ColorType::RGB => bytes.chunks(3).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: 255 }).collect(),
+        png::ColorType::GrayscaleAlpha => bytes.chunks(2).map(|p| Pixel { r: p[0], g: p[0], b: p[0], a: p[1] }).collect(),
+        png::ColorType::Grayscale => bytes.iter().map(|&v| Pixel { r: v, g: v, b: v, a: 255 }).collect(),
+        color_type => bail!("unsupported png colour type {:?}", color_type),
+    };
+
+    Ok((info.width as usize, info.height as usize, pixels))
+}
@@ -0,0 +1,64 @@
+// -----------------------------------------------------------------------------
+//     - Region -
+//     A rectangle of pixels, `x` and `y` are the top left pixel
+// -----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// A single pixel
+    pub fn pixel(x: usize, y: usize) -> Self {
+        Self::new(x, y, 1, 1)
+    }
+
+    /// The smallest region covering both regions
+    pub fn union(self, other: Region) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        Self::new(x, y, right - x, bottom - y)
+    }
+
+    /// The part covered by both regions
+    pub fn intersect(self, other: Region) -> Option<Self> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        match right > x && bottom > y {
+            true => Some(Self::new(x, y, right - x, bottom - y)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn union() {
+        let region = Region::pixel(2, 3).union(Region::pixel(5, 1));
+        assert_eq!(region, Region::new(2, 1, 4, 3));
+        assert_eq!(region.union(Region::pixel(3, 2)), region);
+    }
+
+    #[test]
+    fn intersect() {
+        let region = Region::new(0, 0, 4, 4);
+        assert_eq!(region.intersect(Region::new(2, 3, 5, 5)), Some(Region::new(2, 3, 2, 1)));
+        assert_eq!(region.intersect(Region::new(4, 0, 1, 1)), None);
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::commandline::Transform;
+
+use super::image::Image;
+use super::region::Region;
+
+/// Flip, rotate or shift the pixels in the selection, or in the whole image
+/// if nothing is selected, on the current layer or on every layer.
+///
+/// Rotating a quarter turn needs a square to rotate, unless every layer
+/// of the whole image is rotated, which swaps the width and height of the image.
+pub fn apply(image: &mut Image, transform: Transform, selection: Option<Region>, all_layers: bool) -> Result<()> {
+    let whole = Region::new(0, 0, image.width(), image.height());
+    let region = match selection {
+        Some(selection) => selection.intersect(whole).ok_or_else(|| anyhow!("the selection is outside of the image"))?,
+        None => whole,
+    };
+
+    let (width, height) = (region.width, region.height);
+
+    if let Transform::Rotate(turns) = transform {
+        if turns % 2 == 1 && width != height {
+            if region != whole || !all_layers {
+                bail!("only a square can be rotated a quarter turn, add `all` to rotate every layer of the image");
+            }
+
+            (0..turns).for_each(|_| image.rotate_quarter());
+            return Ok(());
+        }
+    }
+
+    let source = move |x: usize, y: usize| -> Option<(usize, usize)> {
+        match transform {
+            Transform::FlipHorizontal => Some((width - 1 - x, y)),
+            Transform::FlipVertical => Some((x, height - 1 - y)),
+            Transform::Rotate(1) => Some((y, width - 1 - x)),
+            Transform::Rotate(2) => Some((width - 1 - x, height - 1 - y)),
+            Transform::Rotate(_) => Some((width - 1 - y, x)),
+            Transform::Shift { x: dx, y: dy, wrap } => {
+                let (x, y) = (x as i32 - dx, y as i32 - dy);
+                let (width, height) = (width as i32, height as i32);
+
+                match wrap {
+                    true => Some((x.rem_euclid(width) as usize, y.rem_euclid(height) as usize)),
+                    false if x < 0 || y < 0 || x >= width || y >= height => None,
+                    false => Some((x as usize, y as usize)),
+                }
+            }
+        }
+    };
+
+    match all_layers {
+        true => image.layers_mut().iter_mut().for_each(|layer| layer.remap(region, source)),
+        false => image.layer_mut().remap(region, source),
+    }
+
+    Ok(())
+}
@@ -1,50 +1,89 @@
+use std::fs::create_dir_all;
 use std::path::PathBuf;
 use std::sync::mpsc;
-use std::thread;
 use std::time::Duration;
 
 use anyhow::Result;
+use log::error;
 use nightmare::events::EventProxy;
 use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 
-use crate::listener::{Listener, MessageCtx};
-use crate::message::Message;
-
-pub struct FsEvent(PathBuf);
+#[derive(Debug)]
+pub enum FsEvent {
+    Plugin(PathBuf),
+    Config(PathBuf),
+}
 
-pub struct PluginWatcher {
+// -----------------------------------------------------------------------------
+//     - Fs watcher -
+//     Watch the plugin directory and the config files
+// -----------------------------------------------------------------------------
+pub struct FsWatcher {
     watcher: RecommendedWatcher,
     rx: mpsc::Receiver<DebouncedEvent>,
-    proxy: EventProxy<PathBuf>,
-    path: PathBuf,
+    proxy: EventProxy<FsEvent>,
+    plugin_dir: Option<PathBuf>,
+    config_files: Vec<PathBuf>,
 }
 
-impl PluginWatcher {
-    pub fn new(path: impl Into<PathBuf>, proxy: EventProxy<PathBuf>) -> Result<Self> {
+impl FsWatcher {
+    pub fn new(plugin_dir: Option<PathBuf>, config_files: Vec<PathBuf>, proxy: EventProxy<FsEvent>) -> Result<Self> {
         let (tx, rx) = mpsc::channel();
         let watcher = RecommendedWatcher::new(tx, Duration::from_secs(2))?;
         let inst = Self {
             watcher,
             rx,
             proxy,
-            path: path.into(),
+            plugin_dir,
+            config_files,
         };
 
         Ok(inst)
     }
 
     pub fn watch(mut self) -> ! {
-        self.watcher.watch(&self.path, RecursiveMode::Recursive);
+        if let Some(dir) = &self.plugin_dir {
+            drop(self.watcher.watch(dir, RecursiveMode::Recursive));
+        }
+
+        // Watch the directory rather than the file:
+        // the file might not exist yet, and a lot of editors
+        // replace the file rather than writing to it.
+        // The user config directory is created if it's missing,
+        // so a config written there later is still picked up.
+        for file in &self.config_files {
+            if let Some(dir) = file.parent() {
+                if let Err(e) = create_dir_all(dir) {
+                    error!("Failed to create {}: {}", dir.display(), e);
+                }
+                drop(self.watcher.watch(dir, RecursiveMode::NonRecursive));
+            }
+        }
 
         use DebouncedEvent::*;
         loop {
             match self.rx.recv() {
                 Ok(
                     NoticeWrite(pb) | NoticeRemove(pb) | Create(pb) | Write(pb) | Remove(pb)
-                    | Chmod(pb),
-                ) => drop(self.proxy.send_event(pb)),
+                    | Chmod(pb) | Rename(_, pb),
+                ) => {
+                    if let Some(event) = self.event(pb) {
+                        drop(self.proxy.send_event(event));
+                    }
+                }
                 _ => {}
             }
         }
     }
+
+    fn event(&self, path: PathBuf) -> Option<FsEvent> {
+        if self.config_files.contains(&path) {
+            return Some(FsEvent::Config(path));
+        }
+
+        match &self.plugin_dir {
+            Some(dir) if path.starts_with(dir) => Some(FsEvent::Plugin(path)),
+            _ => None,
+        }
+    }
 }
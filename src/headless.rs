@@ -0,0 +1,139 @@
+//! Run command scripts without a window: `mixel --headless --script <file>`.
+//!
+//! Nothing is rendered: commands are applied straight to a `Document`,
+//! and `:w` writes the image to disk.
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use mlua::Lua;
+
+use crate::commandline::{script, Command, Parser};
+use crate::config::Config;
+use crate::document::Document;
+use crate::plugins::exec_with_document;
+
+// -----------------------------------------------------------------------------
+//     - Headless -
+// -----------------------------------------------------------------------------
+pub struct Headless<'a> {
+    config: &'a Config,
+    pub document: Document,
+    lua: Lua,
+    /// Aliases defined with `:command`
+    defined: HashMap<String, String>,
+    /// Number of scripts being sourced
+    depth: usize,
+    /// Set by `:q`, stops every script
+    quit: bool,
+}
+
+impl<'a> Headless<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            document: Document::new(),
+            lua: Lua::new(),
+            defined: HashMap::new(),
+            depth: 0,
+            quit: false,
+        }
+    }
+
+    /// Run every command in a script file, in order.
+    /// Stops at the first command that fails, with the file and line number in the error.
+    pub fn source(&mut self, path: &Path) -> Result<()> {
+        script::source(self, path)
+    }
+
+    pub fn run(&mut self, src: &str) -> Result<()> {
+        let mut aliases = self.config.aliases.clone();
+        aliases.extend(self.defined.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        let command = Parser::new(src).aliases(&aliases).parse()?;
+        self.execute(command)
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Batch(commands) => commands.into_iter().try_for_each(|command| self.execute(command)),
+            Command::Define { name, body } => {
+                self.defined.insert(name, body);
+                Ok(())
+            }
+            Command::Source(path) => self.source(&path),
+            Command::Lua(code) => self.lua(&code),
+            Command::Log(line) => {
+                info!("{}", line);
+                Ok(())
+            }
+            Command::Quit => {
+                self.quit = true;
+                Ok(())
+            }
+            command => self.document.apply(&command),
+        }
+    }
+
+    /// Run Lua code with the same functions as the plugins
+    fn lua(&mut self, code: &str) -> Result<()> {
+        exec_with_document(&self.lua, code, &mut self.document).map_err(|e| anyhow!("lua: {}", e))
+    }
+}
+
+impl script::Sink for Headless<'_> {
+    fn run_command(&mut self, line: String) -> Result<()> {
+        self.run(&line)
+    }
+
+    fn depth(&mut self) -> &mut usize {
+        &mut self.depth
+    }
+
+    fn stopped(&self) -> bool {
+        self.quit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env::temp_dir;
+    use std::fs::{remove_file, write};
+
+    use super::*;
+
+    #[test]
+    fn run_script() {
+        let dir = temp_dir();
+        let script = dir.join("mixel-headless-test.mx");
+        let output = dir.join("mixel-headless-test.png");
+
+        let src = format!(
+            "# Red square\nnew 4 4\ncolour 255 0 0\nput *\nlua putPixel(0, 0)\n:w! {}\n",
+            output.display()
+        );
+        write(&script, src).unwrap();
+
+        let config = Config::from_path(dir.join("mixel-no-config.toml")).unwrap();
+        let mut headless = Headless::new(&config);
+        headless.source(&script).unwrap();
+
+        assert!(output.exists());
+        let image = headless.document.image().unwrap();
+        assert_eq!((image.width(), image.height()), (4, 4));
+
+        remove_file(script).unwrap();
+        remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn error_has_line_number() {
+        let config = Config::from_path(temp_dir().join("mixel-no-config.toml")).unwrap();
+        let mut headless = Headless::new(&config);
+
+        let src = "new 2 2\n\nput 0,0\nnewlayer 1\n";
+        let error = script::run_lines(&mut headless, Path::new("test.mx"), src).unwrap_err();
+        assert_eq!(error.to_string(), "test.mx:4: newlayer doesn't take any arguments");
+    }
+}
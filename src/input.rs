@@ -1,7 +1,12 @@
-use nightmare::events::{ButtonState, MouseButton, Key};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use nightmare::events::{ButtonState, MouseButton, Key, Modifiers};
 use nightmare::Position;
 
 use crate::application::Mode;
+use crate::commandline::Command;
+use crate::config::{format_input, normalise, Action, Config, KeyPress, Match};
 use crate::listener::{Listener, MessageCtx};
 use crate::message::Message;
 use crate::Mouse;
@@ -15,23 +20,34 @@ pub enum Input {
 }
 
 impl Input {
-    pub fn from_char(c: char) -> Input {
-        Input::Char(c)
+    /// Control chars sent while holding ctrl are turned back into their letter,
+    /// so `<C-i>` isn't a tab. Otherwise chars that are also sent as a key
+    /// (tab, return, backspace, escape, delete) are dropped.
+    pub fn from_char(c: char, modifiers: Modifiers) -> Option<Input> {
+        let letter = |c: char| Some(Input::Char((b'a' + c as u8 - 1) as char));
+
+        match c {
+            '\u{1}'..='\u{1a}' if modifiers.ctrl() => letter(c),
+            '\t' | '\n' | '\r' | '\u{8}' | '\u{1b}' | '\u{7f}' => None,
+            '\u{1}'..='\u{1a}' => letter(c),
+            c if c.is_control() => None,
+            c => Some(Input::Char(c)),
+        }
     }
 
+    /// Keys that also produce a char (letters, digits, space) are dropped
+    /// as they arrive through `from_char`.
     pub fn from_key(k: Key) -> Option<Input> {
         match k {
-            Key::Left => Some(Input::Key(k)),
-            Key::Right => Some(Input::Key(k)),
-            Key::Up => Some(Input::Key(k)),
-            Key::Down => Some(Input::Key(k)),
-            Key::Escape => Some(Input::Key(k)),
-            Key::LControl => Some(Input::Key(k)),
-            Key::RControl => Some(Input::Key(k)),
-            Key::LShift => Some(Input::Key(k)),
-            Key::RShift => Some(Input::Key(k)),
-            Key::Back => Some(Input::Key(k)),
-            Key::Return => Some(Input::Key(k)),
+            Key::Left | Key::Right | Key::Up | Key::Down => Some(Input::Key(k)),
+            Key::Home | Key::End | Key::PageUp | Key::PageDown => Some(Input::Key(k)),
+            Key::Insert | Key::Delete => Some(Input::Key(k)),
+            Key::Escape | Key::Back | Key::Return | Key::Tab => Some(Input::Key(k)),
+            Key::LControl | Key::RControl => Some(Input::Key(k)),
+            Key::LShift | Key::RShift => Some(Input::Key(k)),
+            Key::LAlt | Key::RAlt => Some(Input::Key(k)),
+            Key::F1 | Key::F2 | Key::F3 | Key::F4 | Key::F5 | Key::F6 => Some(Input::Key(k)),
+            Key::F7 | Key::F8 | Key::F9 | Key::F10 | Key::F11 | Key::F12 => Some(Input::Key(k)),
             _ => None,
         }
     }
@@ -39,30 +55,377 @@ impl Input {
     pub fn mouse(mouse: Mouse) -> Input {
         Input::Mouse(mouse)
     }
+
+    /// Input that can be part of a key sequence.
+    /// Mouse input and modifier keys on their own are not.
+    pub fn is_key(&self) -> bool {
+        match self {
+            Input::Char(_) => true,
+            Input::Key(Key::LControl) | Input::Key(Key::RControl) => false,
+            Input::Key(Key::LShift) | Input::Key(Key::RShift) => false,
+            Input::Key(Key::LAlt) | Input::Key(Key::RAlt) => false,
+            Input::Key(_) => true,
+            Input::Mouse(_) | Input::Scroll(_) => false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Operator -
+// -----------------------------------------------------------------------------
+/// An operator waits for a motion and is then applied
+/// to every pixel the motion passes over.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Erase,
+    Paint,
+    Yank,
+}
+
+// -----------------------------------------------------------------------------
+//     - Pending -
+//     Count and operator typed so far in normal mode
+// -----------------------------------------------------------------------------
+const MAX_COUNT: usize = 9999;
+
+#[derive(Debug, Default)]
+struct Pending {
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+}
+
+impl Pending {
+    /// Returns true if the char was consumed as part of a count.
+    /// A leading zero is not a count (to leave `0` free for a binding).
+    fn push_digit(&mut self, c: char) -> bool {
+        let digit = match c.to_digit(10) {
+            Some(0) if self.count.is_none() => return false,
+            Some(d) => d as usize,
+            None => return false,
+        };
+
+        let count = self.count.unwrap_or(0) * 10 + digit;
+        self.count = Some(count.min(MAX_COUNT));
+        true
+    }
+
+    fn resolve(&mut self, action: Action) -> Message {
+        let count = self.count.take().unwrap_or(1);
+
+        match (self.operator.take(), action.operator()) {
+            // Start of an operator, wait for the motion
+            (None, Some(op)) => {
+                self.operator = Some((op, count));
+                Message::Noop
+            }
+            // Doubled operator (e.g `dd`) applies to the pixel under the cursor,
+            // or `count` pixels from it (`3dd`, `d3d`)
+            (Some((op, op_count)), Some(next)) if op == next => Message::Operator {
+                operator: op,
+                motion: None,
+                count: (op_count * count).min(MAX_COUNT),
+            },
+            (Some((op, op_count)), None) if action.is_motion() => Message::Operator {
+                operator: op,
+                motion: Some(action),
+                count: (op_count * count).min(MAX_COUNT),
+            },
+            // Anything else cancels the pending operator
+            (Some(_), _) => Message::Noop,
+            (None, None) => match action {
+                Action::Noop => Message::Noop,
+                action if count == 1 => Message::Action(action),
+                action => Message::RepeatAction(action, count),
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count.is_none() && self.operator.is_none()
+    }
+
+    fn clear(&mut self) {
+        self.count = None;
+        self.operator = None;
+    }
 }
 
-pub struct InputToAction(Mode);
+// -----------------------------------------------------------------------------
+//     - Input to action -
+// -----------------------------------------------------------------------------
+pub struct InputToAction {
+    mode: Mode,
+    pending: Pending,
+    /// Keys of a sequence that is not yet complete
+    keys: Vec<KeyPress>,
+    last_key: Instant,
+    /// Everything typed since the last complete action
+    typed: String,
+    /// What was last sent as `Message::PendingKeys`
+    shown: String,
+    /// Actions resolved by the same key as an earlier action,
+    /// sent one per tick
+    queued: VecDeque<Message>,
+}
 
 impl InputToAction {
     pub fn new(mode: Mode) -> Self {
-        Self(mode)
+        Self {
+            mode,
+            pending: Pending::default(),
+            keys: Vec::new(),
+            last_key: Instant::now(),
+            typed: String::new(),
+            shown: String::new(),
+            queued: VecDeque::new(),
+        }
+    }
+
+    fn key(&mut self, key: KeyPress, config: &Config) -> Message {
+        self.keys.push(key);
+        self.last_key = Instant::now();
+
+        let mut messages = self.resolve(config).into_iter().filter(|msg| !matches!(msg, Message::Noop));
+        let first = messages.next().unwrap_or(Message::Noop);
+        self.queued.extend(messages);
+        first
+    }
+
+    /// Resolve the keys typed so far.
+    /// Once they stop matching any binding, the longest bound start of them fires
+    /// (`z` with `z` and `zz` bound, then `l`), and the keys after it are looked up again.
+    fn resolve(&mut self, config: &Config) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        while !self.keys.is_empty() {
+            match config.key_map(self.mode, &self.keys) {
+                Match::Pending | Match::Ambiguous(_) => break,
+                Match::Exact(action) => {
+                    self.keys.clear();
+                    messages.push(self.action(action));
+                }
+                Match::None => {
+                    let bound = (1..self.keys.len()).rev().find_map(|len| match config.key_map(self.mode, &self.keys[..len]) {
+                        Match::Exact(action) | Match::Ambiguous(action) => Some((len, action)),
+                        Match::Pending | Match::None => None,
+                    });
+
+                    // Nothing bound at all drops the first key
+                    let (len, action) = bound.unwrap_or((1, Action::Noop));
+                    self.keys.drain(..len);
+                    messages.push(self.action(action));
+                }
+            }
+        }
+
+        messages
+    }
+
+    /// Resolve the pending keys once the timeout has passed.
+    /// An ambiguous sequence fires the shorter binding.
+    fn timeout(&mut self, ctx: &MessageCtx) -> Message {
+        if self.keys.is_empty() || self.last_key.elapsed() < ctx.config.timeout {
+            return Message::Noop;
+        }
+
+        let action = match ctx.config.key_map(self.mode, &self.keys) {
+            Match::Exact(action) | Match::Ambiguous(action) => action,
+            Match::Pending | Match::None => Action::Noop,
+        };
+
+        self.keys.clear();
+        self.action(action)
+    }
+
+    fn action(&mut self, action: Action) -> Message {
+        match (self.mode, action) {
+            // Parsed by the command line, as that's where the aliases are
+            (_, Action::Command(command)) => {
+                self.pending.clear();
+                Message::Action(Action::Command(command))
+            }
+            (_, Action::Lua(code)) => {
+                self.pending.clear();
+                Message::Command(Command::Lua(code))
+            }
+            (_, Action::InsertMode) => {
+                self.pending.clear();
+                Message::ModeChanged(Mode::Insert)
+            }
+            (_, Action::VisualMode) => {
+                self.pending.clear();
+                Message::ModeChanged(Mode::Visual)
+            }
+            (Mode::Normal, action) => self.pending.resolve(action),
+            (_, Action::Noop) => Message::Noop,
+            (_, action) => Message::Action(action),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.keys.clear();
+    }
+
+    /// Let the status bar know about any change to the pending keys.
+    fn show_pending(&mut self, msg: Message) -> Message {
+        if self.keys.is_empty() && self.pending.is_empty() {
+            self.typed.clear();
+        }
+
+        match msg {
+            Message::Noop if self.typed != self.shown => {
+                self.shown = self.typed.clone();
+                Message::PendingKeys(self.typed.clone())
+            }
+            msg => {
+                self.shown = self.typed.clone();
+                msg
+            }
+        }
     }
 }
 
 impl Listener for InputToAction {
     fn message(&mut self, msg: &Message, context: &mut MessageCtx) -> Message {
-        match (self.0, msg) {
-            (Mode::Normal, Message::Input(input, modifiers)) => {
-                Message::Action(context.config.key_map(*input, *modifiers))
+        let msg = match (self.mode, msg) {
+            (Mode::Normal, Message::Input(Input::Key(Key::Escape), _)) => {
+                self.clear();
+                Message::Noop
+            }
+            // `:` opens the command line, unless it's part of a key sequence like `@:`
+            (Mode::Normal, Message::Input(Input::Char(':'), _)) if self.keys.is_empty() => {
+                Message::ModeChanged(Mode::Command)
+            }
+            (Mode::Normal, Message::Input(Input::Char(c), modifiers))
+                if modifiers.is_empty() && self.keys.is_empty() && self.pending.push_digit(*c) => {
+                self.typed.push(*c);
+                Message::Noop
             }
-            (Mode::Insert, Message::Input(input, modifiers)) => {
-                Message::Action(context.config.key_map(*input, *modifiers))
+            (Mode::Normal, Message::Input(input, modifiers))
+            | (Mode::Insert, Message::Input(input, modifiers))
+            | (Mode::Visual, Message::Input(input, modifiers)) if input.is_key() => {
+                let key = normalise(*input, *modifiers);
+                self.typed.push_str(&format_input(&key));
+                self.key(key, context.config)
             }
+            (_, Message::Tick) if !self.queued.is_empty() => self.queued.pop_front().unwrap_or(Message::Noop),
+            (Mode::Command, Message::Tick) => Message::Noop,
+            (_, Message::Tick) => self.timeout(context),
             (_, Message::ModeChanged(mode)) => {
-                self.0 = *mode;
+                self.mode = *mode;
+                self.clear();
+                Message::Noop
+            }
+            // Pending keys might not be bound anymore
+            (_, Message::ConfigReloaded) => {
+                self.clear();
                 Message::Noop
             }
             _ => Message::Noop,
+        };
+
+        self.show_pending(msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed(pending: &mut Pending, keys: &str, action: Action) -> Message {
+        for c in keys.chars() {
+            assert!(pending.push_digit(c));
         }
+        pending.resolve(action)
+    }
+
+    #[test]
+    fn ctrl_letters() {
+        assert_eq!(Input::from_char('\t', Modifiers::CTRL), Some(Input::Char('i')));
+        assert_eq!(Input::from_char('\u{8}', Modifiers::CTRL), Some(Input::Char('h')));
+        assert_eq!(Input::from_char('\u{17}', Modifiers::CTRL), Some(Input::Char('w')));
+        assert_eq!(Input::from_char('\t', Modifiers::empty()), None);
+        assert_eq!(Input::from_char('\r', Modifiers::empty()), None);
+    }
+
+    #[test]
+    fn count_repeats_action() {
+        let mut pending = Pending::default();
+        let msg = feed(&mut pending, "10", Action::Down);
+        assert!(matches!(msg, Message::RepeatAction(Action::Down, 10)));
+    }
+
+    #[test]
+    fn leading_zero_is_not_a_count() {
+        let mut pending = Pending::default();
+        assert!(!pending.push_digit('0'));
+        assert!(pending.push_digit('1'));
+        assert!(pending.push_digit('0'));
+        assert_eq!(pending.count, Some(10));
+    }
+
+    #[test]
+    fn operator_with_motion() {
+        let mut pending = Pending::default();
+        assert!(matches!(feed(&mut pending, "2", Action::Erase), Message::Noop));
+        let msg = feed(&mut pending, "3", Action::Right);
+        assert!(matches!(
+            msg,
+            Message::Operator { operator: Operator::Erase, motion: Some(Action::Right), count: 6 }
+        ));
+    }
+
+    #[test]
+    fn doubled_operator() {
+        let mut pending = Pending::default();
+        pending.resolve(Action::Yank);
+        let msg = pending.resolve(Action::Yank);
+        assert!(matches!(msg, Message::Operator { operator: Operator::Yank, motion: None, count: 1 }));
+
+        assert!(matches!(feed(&mut pending, "3", Action::Erase), Message::Noop));
+        let msg = feed(&mut pending, "2", Action::Erase);
+        assert!(matches!(msg, Message::Operator { operator: Operator::Erase, motion: None, count: 6 }));
+    }
+
+    #[test]
+    fn non_motion_cancels_operator() {
+        let mut pending = Pending::default();
+        pending.resolve(Action::Paint);
+        assert!(matches!(pending.resolve(Action::CanvasZoomIn), Message::Noop));
+        assert!(matches!(pending.resolve(Action::Left), Message::Action(Action::Left)));
+    }
+
+    fn config(normal: &str) -> Config {
+        let path = std::env::temp_dir().join(format!("mixel-input-test-{}.toml", std::process::id()));
+        std::fs::write(&path, format!("[commands.normal]\n{}", normal)).unwrap();
+        let config = Config::from_path(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        config
+    }
+
+    fn char_key(c: char) -> KeyPress {
+        (Input::Char(c), Modifiers::empty())
+    }
+
+    #[test]
+    fn longest_bound_start_fires() {
+        let config = config("z = \"zoom_in\"\nzz = \"zoom_out\"\n");
+        let mut input = InputToAction::new(Mode::Normal);
+
+        assert!(matches!(input.key(char_key('z'), &config), Message::Noop));
+        assert!(matches!(input.key(char_key('l'), &config), Message::Action(Action::CanvasZoomIn)));
+        assert!(matches!(input.queued.pop_front(), Some(Message::Action(Action::Right))));
+        assert!(input.keys.is_empty());
+    }
+
+    #[test]
+    fn mode_keys_are_bindings() {
+        let config = config("gi = \"fill\"\n");
+        let mut input = InputToAction::new(Mode::Normal);
+
+        assert!(matches!(input.key(char_key('g'), &config), Message::Noop));
+        assert!(matches!(input.key(char_key('i'), &config), Message::Action(Action::Fill)));
+        assert!(matches!(input.key(char_key('i'), &config), Message::ModeChanged(Mode::Insert)));
     }
 }
@@ -1,8 +1,9 @@
 use std::mem::replace;
 use std::fmt;
 
-use nightmare::{Position, Size, Rect, Point};
+use nightmare::{Position, Size};
 use crate::canvas::Container;
+use crate::commandline::Direction;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Split {
@@ -10,11 +11,14 @@ pub enum Split {
     Vert,
 }
 
+/// A split is never resized below this, along the direction of the split
+const MIN_SIZE: i32 = 1;
+
 fn placeholder() -> Layout {
     Layout::Leaf { id: std::usize::MAX, size: Size::new(1, 1), pos: Position::zeros() }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Layout {
     Leaf { id: usize, size: Size, pos: Position },
     Branch { left: Box<Layout>, right: Box<Layout>, size: Size, pos: Position },
@@ -66,7 +70,6 @@ impl Layout {
     }
 
     pub fn split(&mut self, left_id: usize, right_id: usize, split: Split) {
-        eprintln!("L: {} | R: {}", left_id, right_id);
         if let Some(node) = self.find_node(left_id) {
             match node {
                 Layout::Leaf { size, pos, .. } => {
@@ -91,15 +94,119 @@ impl Layout {
                     };
 
                 }
-                Layout::Branch { left, right, .. } => unreachable!(),
+                Layout::Branch { .. } => unreachable!(),
             }
         }
     }
 
+    /// Resize a leaf by moving the edges of the closest splits
+    /// across the width and across the height.
+    /// The space is taken from, or given to, the other side of each split.
     pub fn resize(&mut self, node_id: usize, new_size: Size) {
-        if let Some(n) = self.find_node(node_id) {
-            n.set_size(new_size);
-            self.rebuild();
+        if let Some((_, size, _)) = self.leaves().into_iter().find(|(id, ..)| *id == node_id) {
+            self.grow(node_id, new_size.width - size.width, new_size.height - size.height);
+        }
+    }
+
+    /// Grow a leaf by `width` and `height`, or shrink it with negative numbers
+    pub fn grow(&mut self, node_id: usize, width: i32, height: i32) {
+        if width != 0 {
+            self.resize_along(node_id, Split::Vert, width);
+        }
+
+        if height != 0 {
+            self.resize_along(node_id, Split::Horz, height);
+        }
+
+        self.rebuild();
+    }
+
+    /// Give `amount` more space to the side holding `node_id`
+    /// of the closest split in the direction of `split`.
+    /// Returns false if there is no such split.
+    fn resize_along(&mut self, node_id: usize, split: Split, amount: i32) -> bool {
+        let (left, right, size) = match self {
+            Layout::Leaf { .. } => return false,
+            Layout::Branch { left, right, size, .. } => (left, right, *size),
+        };
+
+        let in_left = left.contains(node_id);
+        if !in_left && !right.contains(node_id) {
+            return false;
+        }
+
+        let found = match in_left {
+            true => left.resize_along(node_id, split, amount),
+            false => right.resize_along(node_id, split, amount),
+        };
+
+        if found {
+            return true;
+        }
+
+        let amount = match in_left {
+            true => amount,
+            false => -amount,
+        };
+
+        let vertical = left.pos().x != right.pos().x;
+        let mut left_size = left.size();
+        match (split, vertical) {
+            (Split::Vert, true) => {
+                left_size.width = (left_size.width + amount).max(MIN_SIZE).min(size.width - MIN_SIZE);
+            }
+            (Split::Horz, false) => {
+                left_size.height = (left_size.height + amount).max(MIN_SIZE).min(size.height - MIN_SIZE);
+            }
+            _ => return false,
+        }
+
+        left.set_size(left_size);
+        true
+    }
+
+    /// Give every leaf the same share of the space.
+    /// Splits in the same direction share it between all their leaves,
+    /// so three side by side leaves get a third each.
+    pub fn equalise(&mut self) {
+        if let Layout::Branch { left, right, size, .. } = self {
+            let vertical = left.pos().x != right.pos().x;
+            let (left_count, right_count) = (left.count(vertical), right.count(vertical));
+
+            let mut left_size = left.size();
+            match vertical {
+                true => left_size.width = size.width * left_count / (left_count + right_count),
+                false => left_size.height = size.height * left_count / (left_count + right_count),
+            }
+            left.set_size(left_size);
+        }
+
+        self.rebuild();
+
+        if let Layout::Branch { left, right, .. } = self {
+            left.equalise();
+            right.equalise();
+        }
+    }
+
+    /// Number of leaves side by side, or one above the other if not `vertical`
+    fn count(&self, vertical: bool) -> i32 {
+        match self {
+            Layout::Leaf { .. } => 1,
+            Layout::Branch { left, right, .. } => {
+                let (left_count, right_count) = (left.count(vertical), right.count(vertical));
+                match (left.pos().x != right.pos().x) == vertical {
+                    true => left_count + right_count,
+                    false => left_count.max(right_count),
+                }
+            }
+        }
+    }
+
+    fn contains(&self, node_id: usize) -> bool {
+        match self {
+            Layout::Leaf { id, .. } => *id == node_id,
+            Layout::Branch { left, right, .. } => left.contains(node_id) || right.contains(node_id),
         }
     }
 
@@ -113,11 +220,17 @@ impl Layout {
             }
         };
 
+        // The sibling takes the place of the parent,
+        // so it also takes the space of the parent.
+        let (size, pos) = (self.size(), self.pos());
+
         // If the left node id matches the `node_id` then
         // swap the parent for the right node
         if matches!(left.as_mut(), Layout::Leaf {id, ..} if *id == node_id) {
             let new_parent = replace(right.as_mut(), placeholder());
             *self = new_parent;
+            self.set_size(size);
+            self.set_pos(pos);
             return true;
         }
     
@@ -126,6 +239,8 @@ impl Layout {
         if matches!(right.as_mut(), Layout::Leaf {id, ..} if *id == node_id) {
             let new_parent = replace(left.as_mut(), placeholder());
             *self = new_parent;
+            self.set_size(size);
+            self.set_pos(pos);
             return true;
         }
 
@@ -143,19 +258,27 @@ impl Layout {
             Layout::Branch { left, right, size, pos } => {
                 *pos = parent_pos;
 
+                // Check the direction of the split before moving anything
+                let vertical = left.pos().x != right.pos().x;
                 left.set_pos(parent_pos);
                 let (right_pos, right_size) = {
                     let mut right_pos = parent_pos;
                     let mut right_size = *size;
-                    if left.pos().x != right.pos().x {
-                        let width = left.size().width;
+                    // The left node keeps its size along the split,
+                    // and fills the parent across it.
+                    let mut left_size = left.size();
+                    if vertical {
+                        left_size.height = size.height;
+                        let width = left_size.width;
                         right_pos.x += width;
                         right_size.width -= width;
                     } else {
-                        let height = left.size().height;
+                        left_size.width = size.width;
+                        let height = left_size.height;
                         right_pos.y += height;
                         right_size.height -= height;
                     }
+                    left.set_size(left_size);
                     (right_pos, right_size)
                 };
 
@@ -172,6 +295,71 @@ impl Layout {
         self.set_size(size);
     }
 
+    /// Every leaf id with its size and position, left to right
+    pub fn leaves(&self) -> Vec<(usize, Size, Position)> {
+        match self {
+            Layout::Leaf { id, size, pos } => vec![(*id, *size, *pos)],
+            Layout::Branch { left, right, .. } => {
+                let mut leaves = left.leaves();
+                leaves.extend(right.leaves());
+                leaves
+            }
+        }
+    }
+
+    /// Lower every id above `removed` by one, to keep the ids
+    /// in step with the containers once a container is removed.
+    pub fn remove_id(&mut self, removed: usize) {
+        match self {
+            Layout::Leaf { id, .. } if *id > removed => *id -= 1,
+            Layout::Leaf { .. } => {}
+            Layout::Branch { left, right, .. } => {
+                left.remove_id(removed);
+                right.remove_id(removed);
+            }
+        }
+    }
+
+    /// The closest leaf next to `node_id`, in the given direction.
+    /// Only leaves that line up with the node are considered,
+    /// and the one closest to the middle of the node wins.
+    ///
+    /// `y` grows upwards, like the viewports.
+    pub fn neighbour(&self, node_id: usize, direction: Direction) -> Option<usize> {
+        let leaves = self.leaves();
+        let rect = |size: Size, pos: Position| {
+            (pos.x as f32, pos.y as f32, pos.x as f32 + size.width as f32, pos.y as f32 + size.height as f32)
+        };
+
+        let (x0, y0, x1, y1) = leaves
+            .iter()
+            .find(|(id, ..)| *id == node_id)
+            .map(|(_, size, pos)| rect(*size, *pos))?;
+
+        leaves
+            .iter()
+            .filter(|(id, ..)| *id != node_id)
+            .filter_map(|(id, size, pos)| {
+                let (ox0, oy0, ox1, oy1) = rect(*size, *pos);
+                let overlap_x = ox0 < x1 && ox1 > x0;
+                let overlap_y = oy0 < y1 && oy1 > y0;
+                let centre_x = ((ox0 + ox1) - (x0 + x1)).abs();
+                let centre_y = ((oy0 + oy1) - (y0 + y1)).abs();
+
+                let (distance, offset) = match direction {
+                    Direction::Left if overlap_y && ox1 <= x0 => (x0 - ox1, centre_y),
+                    Direction::Right if overlap_y && ox0 >= x1 => (ox0 - x1, centre_y),
+                    Direction::Up if overlap_x && oy0 >= y1 => (oy0 - y1, centre_x),
+                    Direction::Down if overlap_x && oy1 <= y0 => (y0 - oy1, centre_x),
+                    _ => return None,
+                };
+
+                Some((*id, distance, offset))
+            })
+            .min_by(|a, b| (a.1, a.2).partial_cmp(&(b.1, b.2)).unwrap())
+            .map(|(id, ..)| id)
+    }
+
     pub fn layout(&self, containers: &mut [Container]) {
         match self {
             Layout::Leaf { id, size, pos } => {
@@ -198,7 +386,7 @@ enum LeafType {
 }
 
 impl Layout {
-    fn display(&self, level: usize, f: &mut fmt::Formatter<'_>, leaf_type: LeafType) {
+    fn display(&self, level: usize, f: &mut fmt::Formatter<'_>, leaf_type: LeafType) -> fmt::Result {
         let side = match leaf_type {
             LeafType::Root => "root",
             LeafType::Left => "left",
@@ -209,22 +397,20 @@ impl Layout {
 
         match self {
             Layout::Leaf { id, size, pos } => {
-                write!(f, "{} {}{} leaf: {} ({:?} | {:?})\n", level, spacer, side, id, pos, size);
-            },
+                writeln!(f, "{} {}{} leaf: {} ({:?} | {:?})", level, spacer, side, id, pos, size)
+            }
             Layout::Branch { left, right, size, pos } => {
-                write!(f, "{} {}{} branch ({:?} | {:?})\n", level, spacer, side, pos, size); 
-                left.display(level + 1, f, LeafType::Left);
-                right.display(level + 1, f, LeafType::Right);
+                writeln!(f, "{} {}{} branch ({:?} | {:?})", level, spacer, side, pos, size)?;
+                left.display(level + 1, f, LeafType::Left)?;
+                right.display(level + 1, f, LeafType::Right)
             }
         }
-
     }
 }
 
 impl fmt::Display for Layout {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.display(0, f, LeafType::Root);
-        Ok(())
+        self.display(0, f, LeafType::Root)
     }
 }
 
@@ -242,12 +428,12 @@ mod test {
 
         // Left branch
         let expected = (0, Size::new(20, 5), Position::zeros());
-        let actual = tree.layout()[0];
+        let actual = tree.leaves()[0];
         assert_eq!(expected, actual);
 
         // Right branch
         let expected = (100, Size::new(20, 15), Position::new(0, 5));
-        let actual = tree.layout()[1];
+        let actual = tree.leaves()[1];
         assert_eq!(expected, actual);
     }
 
@@ -258,10 +444,68 @@ mod test {
         tree.split(100, 200, Split::Vert);
         tree.resize(100, Size::new(3, 10));
 
-        let layout = tree.layout();
+        let layout = tree.leaves();
         let expected = (200, Size::new(17, 10), Position::new(3, 10));
         let actual = layout[2];
         assert_eq!(expected, actual);
     }
-}
 
+    #[test]
+    fn collapse_gives_the_space_to_the_sibling() {
+        let mut tree = Layout::Leaf { id: 0, pos: Position::zeros(), size: Size::new(20, 20) };
+        tree.split(0, 1, Split::Horz);
+        tree.split(1, 2, Split::Vert);
+
+        assert!(tree.collapse(0));
+        tree.rebuild();
+
+        let leaves = tree.leaves();
+        assert_eq!(leaves[0], (1, Size::new(10, 20), Position::zeros()));
+        assert_eq!(leaves[1], (2, Size::new(10, 20), Position::new(10, 0)));
+
+        tree.remove_id(0);
+        assert_eq!(tree.leaves()[1].0, 1);
+    }
+
+    #[test]
+    fn neighbours() {
+        let mut tree = Layout::Leaf { id: 0, pos: Position::zeros(), size: Size::new(20, 20) };
+        tree.split(0, 1, Split::Vert);
+        tree.split(1, 2, Split::Horz);
+
+        assert_eq!(tree.neighbour(0, Direction::Right), Some(1));
+        assert_eq!(tree.neighbour(1, Direction::Up), Some(2));
+        assert_eq!(tree.neighbour(2, Direction::Down), Some(1));
+        assert_eq!(tree.neighbour(2, Direction::Left), Some(0));
+        assert_eq!(tree.neighbour(0, Direction::Left), None);
+    }
+
+    #[test]
+    fn resize_right_and_nested() {
+        let mut tree = Layout::Leaf { id: 0, pos: Position::zeros(), size: Size::new(20, 20) };
+        tree.split(0, 1, Split::Vert);
+        tree.split(1, 2, Split::Horz);
+
+        // The closest split across the width is the root
+        tree.resize(2, Size::new(15, 10));
+        let leaves = tree.leaves();
+        assert_eq!(leaves[0], (0, Size::new(5, 20), Position::zeros()));
+        assert_eq!(leaves[2], (2, Size::new(15, 10), Position::new(5, 10)));
+
+        // Never smaller than the minimum
+        tree.resize(1, Size::new(15, 100));
+        assert_eq!(tree.leaves()[2], (2, Size::new(15, 1), Position::new(5, 19)));
+    }
+
+    #[test]
+    fn equalise() {
+        let mut tree = Layout::Leaf { id: 0, pos: Position::zeros(), size: Size::new(30, 20) };
+        tree.split(0, 1, Split::Vert);
+        tree.split(1, 2, Split::Vert);
+        tree.resize(0, Size::new(3, 20));
+        tree.equalise();
+
+        let widths = tree.leaves().iter().map(|(_, size, _)| size.width).collect::<Vec<_>>();
+        assert_eq!(widths, vec![10, 10, 10]);
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use nightmare::{Context, VertexData, Viewport};
 use nightmare::render2d::{SimpleRenderer, Model};
 
-// use crate::border::Textures;
+use crate::border::Textures;
 use crate::config::Config;
 use crate::message::Message;
 
@@ -20,6 +20,5 @@ pub struct MessageCtx<'a> {
     pub context: &'a mut Context,
     pub canvas_viewport: &'a Viewport,
     pub app_viewport: &'a Viewport,
-    // pub textures: &'a Textures, // TODO: if these are the border textures, why aren't they called border_textures
-    // pub border_renderer: &'a SimpleRenderer<Model>,
+    pub border_textures: &'a Textures,
 }
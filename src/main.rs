@@ -3,33 +3,37 @@ use std::thread;
 use anyhow::Result;
 use log::error;
 use nightmare::events::{ButtonState, Event, EventLoop, LoopAction, Modifiers, MouseButton};
-use nightmare::pixels::Pixel;
 use nightmare::{Context, Position, Size};
 use pretty_env_logger;
 
-// pub mod plugins;
+pub mod plugins;
 
 mod application;
-// mod border;
-// mod canvas;
+mod args;
+mod border;
+mod canvas;
 mod commandline;
 mod config;
 // mod console;
 mod coords;
-// mod fsevents;
+mod document;
+mod fsevents;
+mod headless;
 mod input;
-// mod layout;
+mod layout;
 mod listener;
 mod message;
 mod mouse;
 mod node;
-// mod status;
+mod status;
 
 use application::App;
+use args::Args;
 use config::Config;
 use input::Input;
 use message::Message;
-// use fsevents::PluginWatcher;
+use fsevents::{FsEvent, FsWatcher};
+use headless::Headless;
 pub use node::Node;
 pub use mouse::Mouse;
 pub use coords::Coords;
@@ -37,7 +41,35 @@ pub use coords::Coords;
 fn main() -> Result<()> {
     pretty_env_logger::init();
 
-    let config = Config::from_path("config")?;
+    let args = Args::from_env()?;
+
+    if args.help {
+        print!("{}", Args::usage());
+        return Ok(());
+    }
+
+    if args.check_config {
+        match Config::load() {
+            Ok(_) => {
+                println!("Config is valid");
+                return Ok(());
+            }
+            Err(e) => {
+                eprint!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let config = Config::load()?;
+
+    if let (true, Some(path)) = (args.headless, &args.script) {
+        if let Err(e) = Headless::new(&config).source(path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     let (el, mut context) = Context::builder("Mixel: the modal pixel editor")
         .vsync(true)
@@ -52,23 +84,32 @@ fn main() -> Result<()> {
     let window_size = context.window_size();
     let mut app = App::new(config, window_size, &mut context)?;
 
+    if let Some(path) = &args.script {
+        app.run_script(path, &mut context);
+    }
+
     // Input specifics
     let mut modifiers = Modifiers::empty();
     let mut mouse = Mouse::new();
 
     let proxy = eventloop.proxy();
 
-    // thread::spawn(move || {
-    //     let watcher = PluginWatcher::new("plugins", proxy).unwrap();
-    //     watcher.watch();
-    // });
+    thread::spawn(move || {
+        match FsWatcher::new(config::plugin_dir(), config::config_files(), proxy) {
+            Ok(watcher) => watcher.watch(),
+            Err(e) => error!("Failed to watch plugins and config: {:?}", e),
+        }
+    });
 
     // Event loop
     eventloop.run(move |event| {
         match event {
-            Event::UserEvent(path) => {
+            Event::UserEvent(FsEvent::Plugin(path)) => {
                 app.reload_plugins(path, &mut context);
             }
+            Event::UserEvent(FsEvent::Config(_)) => {
+                app.reload_config(&mut context);
+            }
             Event::MouseWheel { y, .. } => {
                 app.input(Input::Scroll(y as i32), modifiers, &mut context);
             }
@@ -83,8 +124,10 @@ fn main() -> Result<()> {
             }
             Event::Modifier(m) => modifiers = m,
             Event::Char(c) => {
-                if let Err(e) = app.input(Input::from_char(c), modifiers, &mut context) {
-                    error!("Failed to handle input: {:?}", e);
+                if let Some(input) = Input::from_char(c, modifiers) {
+                    if let Err(e) = app.input(input, modifiers, &mut context) {
+                        error!("Failed to handle input: {:?}", e);
+                    }
                 }
             }
             Event::Key {
@@ -102,16 +145,9 @@ fn main() -> Result<()> {
                 }
             }
             Event::Draw(_dt) => {
-                // Clear the background with Nypsiee blue
-                context.clear(
-                    Pixel {
-                        r: 12,
-                        g: 34,
-                        b: 56,
-                        a: 255,
-                    }
-                    .into(),
-                );
+                app.tick(&mut context);
+
+                context.clear(app.clear_colour().into());
                 app.render(&mut context);
                 context.swap_buffers();
             }
@@ -4,10 +4,10 @@ use nightmare::events::{MouseButton, ButtonState, Modifiers};
 use nightmare::{Position, Size};
 
 use crate::application::Mode;
-// use crate::canvas::LayerId;
-use crate::commandline::Command;
+use crate::canvas::LayerId;
+use crate::commandline::{Candidates, Command};
 use crate::config::Action;
-use crate::input::Input;
+use crate::input::{Input, Operator};
 use crate::{Mouse, Coords};
 
 #[derive(Debug)]
@@ -17,10 +17,18 @@ pub enum Message {
     Resize(Size),
     ModeChanged(Mode),
     Command(Command),
+    /// A command failed to parse
+    CommandError(String),
     Action(Action),
+    RepeatAction(Action, usize),
+    Operator { operator: Operator, motion: Option<Action>, count: usize },
     CursorCoords(Coords),
-    // LayerChanged { layer: LayerId, total_layers: usize },
+    PendingKeys(String),
+    Candidates(Candidates),
+    LayerChanged { layer: LayerId, total_layers: usize },
     ReloadPlugin(PathBuf),
+    ConfigReloaded,
+    Tick,
     Noop,
 }
 
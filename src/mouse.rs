@@ -1,6 +1,7 @@
 use anyhow::Result;
 use nightmare::events::{ButtonState, MouseButton};
-use nightmare::{Context, Texture, Position, VertexData, Viewport};
+use nightmare::{Context, Texture, Position, Size, VertexData, Viewport};
+use nightmare::pixels::{Pixel, Pixels};
 use nightmare::render2d::{SimpleRenderer, Model};
 
 use crate::listener::{Listener, MessageCtx};
@@ -42,7 +43,10 @@ pub struct MouseCursor {
 
 impl MouseCursor {
     pub fn new(ctx: &mut MessageCtx) -> Result<Self> {
-        let texture = Texture::from_disk("cursor.png")?;
+        let texture = match &ctx.config.ui.cursor {
+            Some(path) => Texture::from_disk(path)?,
+            None => crosshair(),
+        };
         let renderer = SimpleRenderer::new(ctx.context, ctx.app_viewport.view_projection())?;
         let mut node = Node::new(&texture);
         node.sprite.z_index = 10;
@@ -59,6 +63,25 @@ impl MouseCursor {
 
 }
 
+/// Default cursor when no cursor image is set in the config
+fn crosshair() -> Texture {
+    const SIZE: usize = 15;
+    const MID: usize = SIZE / 2;
+
+    let size = Size::new(SIZE as f32, SIZE as f32);
+    let mut pixels = Pixels::from_pixel(Pixel::transparent(), size);
+    pixels.iter_mut().enumerate().for_each(|(i, pixel)| {
+        let (x, y) = (i % SIZE, i / SIZE);
+        match (x, y) {
+            (MID, MID) => {}
+            (MID, _) | (_, MID) => *pixel = Pixel::white(),
+            _ => {}
+        }
+    });
+
+    Texture::default_with_data(size, pixels.as_bytes())
+}
+
 impl Listener for MouseCursor {
     fn message(&mut self, msg: &Message, ctx: &mut MessageCtx) -> Message {
         if let Message::Input(Input::Mouse(mouse), _) = msg {
@@ -1,10 +1,5 @@
-use std::ops::{Div, MulAssign};
-
-use nightmare::{VertexData, Sprite, Transform, Texture, create_model_matrix};
+use nightmare::{Sprite, Transform, Texture, create_model_matrix};
 use nightmare::render2d::Model;
-use nalgebra::Scalar;
-use num_traits::cast::NumCast;
-use num_traits::{One, Zero};
 
 /// Node to keep track of sprites and transforms.
 #[derive(Debug, Copy, Clone)]
@@ -38,12 +33,4 @@ impl Node {
         let matrix = create_model_matrix(&self.sprite, &self.transform);
         Model::new(matrix, self.sprite.texture_rect)
     }
-
-    /// Pass in the parent nodes transform
-    pub fn relative_vertex_data(&self, transform: &Transform) -> Model {
-        todo!()
-        // let mut model = self.model();
-        // vd.make_relative(transform);
-        // vd
-    }
 }
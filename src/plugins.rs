@@ -1,14 +1,16 @@
 use std::cell::RefCell;
-use std::env::{set_current_dir, current_dir};
-use std::fs::{read, read_dir, DirEntry};
-use std::path::{Component, Path};
+use std::fs::{create_dir_all, read, read_dir};
+use std::path::Path;
 
+use log::info;
 use mlua::prelude::*;
-use mlua::{Lua, Result, Function, Variadic};
+use mlua::{Lua, Result};
 use nightmare::Position;
 use nightmare::pixels::Pixel;
 
-use crate::canvas::Containers;
+use crate::commandline::{Command, Target};
+use crate::config::plugin_dir;
+use crate::document::Document;
 
 #[derive(Debug)]
 pub enum Arg {
@@ -57,25 +59,26 @@ impl PluginCall {
 // -----------------------------------------------------------------------------
 //     - Plugin -
 // -----------------------------------------------------------------------------
+/// Run the `autoload.lua` of a plugin.
+/// The plugin directory is in the `plugin_dir` global while it runs,
+/// and on `package.path` so the plugin can `require` its own modules.
 fn load_plugin(lua: &Lua, path: &Path) -> Result<()> {
-    let cwd = current_dir().unwrap();
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let dir = path.to_string_lossy();
 
-    let name = match path.components().last() {
-        Some(Component::Normal(name)) => name.to_str().unwrap(),
-        _ => return Ok(()), // TODO: meh, fix this you lazy sausage
-    };
+    let plugin_src = read(path.join("autoload.lua"))?;
 
-    let path = path.join("autoload.lua");
-    let plugin_src = read(path)?;
+    let globals = lua.globals();
+    globals.set("plugin_dir", dir.as_ref())?;
+    let package: LuaTable = globals.get("package")?;
+    let package_path: String = package.get("path")?;
+    package.set("path", format!("{}/?.lua;{}", dir, package_path))?;
 
-    let new_path = cwd.join("plugins").join(name);
-    set_current_dir(new_path);
-    match lua.load(&plugin_src).exec() {
-        Ok(_) => eprintln!("Loaded: {}", name),
-        Err(e) => eprintln!("Loading {} failed: {:?}", name, e),
-    }
-    set_current_dir(cwd);
+    lua.load(&plugin_src)
+        .exec()
+        .map_err(|e| LuaError::external(format!("failed to load plugin {}: {}", name, e)))?;
 
+    info!("Loaded plugin: {}", name);
     Ok(())
 }
 
@@ -87,14 +90,18 @@ impl Plugin {
     pub fn new() -> Result<Self> {
         let lua = Lua::new();
 
-        // TODO: It's obvious that we shouldn't do this:
-        //       Plugins should be read from XDG_CONFIG/mixel/plugins
-        //       and make sure the dir exists (create?)
-        let plugins = read_dir("plugins")?;
+        let dir = match plugin_dir() {
+            Some(dir) => dir,
+            None => return Ok(Self { lua }),
+        };
+
+        create_dir_all(&dir)?;
+
+        let plugins = read_dir(dir)?;
         for entry in plugins {
             let path = entry?.path();
             if path.is_dir() {
-                load_plugin(&lua, &path);
+                load_plugin(&lua, &path)?;
             }
         }
 
@@ -105,43 +112,29 @@ impl Plugin {
         Ok(inst)
     }
 
-    pub fn reload(&mut self, path: impl AsRef<Path>) {
+    pub fn reload(&mut self, _path: impl AsRef<Path>) -> Result<()> {
         // TODO: we are now reloading ALL plugins.
         //       That's silly.
-        if let Ok(inst) = Self::new() {
-            *self = inst;
-        }
+        *self = Self::new()?;
+        Ok(())
     }
 
-    // TODO add app context, that contains viewport, app things
-    pub fn exec_code(&mut self, code: &str, containers: &mut Containers) -> LuaResult<()> {
-        let containers = RefCell::new(containers);
-        self.lua.scope(|scope| {
-            let globals = self.lua.globals();
-
-            let f = scope.create_function_mut(|_, (x, y): (i32, i32)| {
-                let mut containers = containers.borrow_mut();
-                // containers.draw(Position::new(x, y));
-                Ok(())
-            }).unwrap();
-            globals.set("putPixel", f);
-
-            let f = scope.create_function_mut(|_, (r, g, b): (u8, u8, u8)| {
-                let mut containers = containers.borrow_mut();
-                let pixel = Pixel { r, g, b, ..Default::default() };
-                containers.set_colour(pixel);
-                Ok(())
-            }).unwrap();
-            globals.set("setColor", f);
-
-            match self.lua.load(code).exec() {
-                Ok(_) => {}
-                Err(e) =>  eprintln!("Lua err: {:?}", e),
-            }
-            Ok(())
-        });
+    /// Names of all global values, used to complete `:lua`
+    pub fn globals(&self) -> Vec<String> {
+        let mut names = self
+            .lua
+            .globals()
+            .pairs::<String, LuaValue>()
+            .filter_map(|pair| pair.ok().map(|(name, _)| name))
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names
+    }
 
-        Ok(())
+    // TODO add app context, that contains viewport, app things
+    pub fn exec_code(&mut self, code: &str, document: &mut Document) -> LuaResult<()> {
+        exec_with_document(&self.lua, code, document)
     }
 
     // pub fn exec(&mut self, call: &PluginCall) -> LuaResult<()> {
@@ -157,6 +150,38 @@ impl Plugin {
     // }
 }
 
+/// Run Lua code with access to the document:
+/// `putPixel(x, y)`, `clearPixel(x, y)` and `setColor(r, g, b)`.
+/// Shared by the plugins and headless scripts.
+pub fn exec_with_document(lua: &Lua, code: &str, document: &mut Document) -> Result<()> {
+    let document = RefCell::new(document);
+    lua.scope(|scope| {
+        let globals = lua.globals();
+
+        let put = scope.create_function_mut(|_, (x, y): (f32, f32)| {
+            let mut document = document.borrow_mut();
+            let _ = document.apply(&Command::Put(Target::Pixel(Position::new(x, y))));
+            Ok(())
+        })?;
+        globals.set("putPixel", put)?;
+
+        let clear = scope.create_function_mut(|_, (x, y): (f32, f32)| {
+            let mut document = document.borrow_mut();
+            let _ = document.apply(&Command::Clear(Target::Pixel(Position::new(x, y))));
+            Ok(())
+        })?;
+        globals.set("clearPixel", clear)?;
+
+        let set_colour = scope.create_function_mut(|_, (r, g, b): (u8, u8, u8)| {
+            document.borrow_mut().colour = Pixel { r, g, b, a: 255 };
+            Ok(())
+        })?;
+        globals.set("setColor", set_colour)?;
+
+        lua.load(code).exec()
+    })
+}
+
 fn to_lua<'a>(lua: &'a Lua, arg: &'a Arg) -> LuaValue<'a> {
     match arg {
         Arg::String(s) => s.clone().to_lua(lua).unwrap(),
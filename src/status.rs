@@ -1,21 +1,28 @@
 use anyhow::Result;
 use log::error;
-use nightmare::text::{Text, WordWrap};
-use nightmare::{Context, Position, Size, VertexData, Viewport};
+use nightmare::text::{default_font_shader, Text, WordWrap};
+use nightmare::{Context, Position, Size, Viewport};
 use nightmare::render2d::{SimpleRenderer, Model};
 
 use crate::application::Mode;
 use crate::canvas::LayerId;
-use crate::commandline::Command;
+use crate::config::Ui;
 use crate::listener::{Listener, MessageCtx};
 use crate::message::Message;
 use crate::Coords;
 
+// -----------------------------------------------------------------------------
+//     - Status -
+//     Cursor coords, mode, layer and the keys typed so far,
+//     shown at the top of the window
+// -----------------------------------------------------------------------------
 pub struct Status {
     dirty: bool,
+    font_size: f32,
     text: Text,
     mode: Mode,
     cursor_coords: Coords,
+    pending_keys: String,
     layer: LayerId,
     total_layers: usize,
     renderer: SimpleRenderer<Model>,
@@ -23,29 +30,20 @@ pub struct Status {
 }
 
 impl Status {
-    pub fn new(size: Size, context: &mut Context) -> Result<Self> {
-        let font_size = 18.0;
-        let position = {
-            Position::new(10.0, size.height as f32 - 10.0 - font_size * 2.0)
-        };
-
-        let mut text = Text::from_path(
-            "/usr/share/fonts/TTF/Hack-Regular.ttf",
-            font_size,
-            WordWrap::NoWrap,
-            context,
-        )?;
-
-        text.position(position);
-        text.z_index(9999);
+    pub fn new(size: Size, ui: &Ui, context: &mut Context) -> Result<Self> {
+        let text = status_text(size, ui, context)?;
 
         let viewport = Viewport::new(Position::zeros(), size);
-        let renderer = SimpleRenderer::new(context, viewport.view_projection())?;
+        let shader = default_font_shader()?;
+        let mut renderer = SimpleRenderer::new(context, viewport.view_projection())?;
+        renderer.set_shader(shader, viewport.view_projection(), context);
 
-        let mut inst = Self {
+        let inst = Self {
             dirty: true,
+            font_size: ui.font_size,
             text,
-            cursor_coords: Coords::zeros(),
+            cursor_coords: Coords::zero(),
+            pending_keys: String::new(),
             mode: Mode::Normal,
             layer: LayerId::from_display(1),
             total_layers: 1,
@@ -56,22 +54,46 @@ impl Status {
         Ok(inst)
     }
 
-    fn update_text(&mut self) {
+    /// Make the text again after the config changed, for a new font or font size
+    fn reload_font(&mut self, ui: &Ui, context: &mut Context) -> Result<()> {
+        self.text = status_text(*self.viewport.size(), ui, context)?;
+        self.font_size = ui.font_size;
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn update_text(&mut self, context: &mut Context) {
         let text = format!(
-            "x: {} y: {} | mode: {:?} | layer: {}/{}",
+            "x: {} y: {} | mode: {:?} | layer: {}/{} {}",
             self.cursor_coords.0.x,
             self.cursor_coords.0.y,
             self.mode,
             self.layer.as_display(),
             self.total_layers,
+            self.pending_keys,
         );
 
-        if let Err(e) = self.text.set_text(text) {
+        if let Err(e) = self.text.set_text(&text) {
             error!("Failed to update text: {:?}", e);
         }
+
+        let models = self.text.models();
+        self.renderer.load_data(&models, context);
     }
 }
 
+fn status_text(size: Size, ui: &Ui, context: &mut Context) -> Result<Text> {
+    let mut text = Text::from_path(ui.font_path()?, ui.font_size, WordWrap::NoWrap, context)?;
+    text.position(text_position(size, ui.font_size));
+    text.z_index(9999);
+    Ok(text)
+}
+
+/// Top left of the window, below the edge
+fn text_position(size: Size, font_size: f32) -> Position {
+    Position::new(10.0, size.y - 10.0 - font_size * 2.0)
+}
+
 // -----------------------------------------------------------------------------
 //     - Listener -
 // -----------------------------------------------------------------------------
@@ -82,38 +104,58 @@ impl Listener for Status {
                 self.mode = *mode;
                 self.dirty = true;
             }
-            Message::Resize(ref size) => self.viewport.resize(*size),
+            Message::Resize(size) => {
+                self.viewport.resize(*size);
+                self.text.position(text_position(*size, self.font_size));
+                self.dirty = true;
+            }
             Message::CursorCoords(coords) => {
                 self.cursor_coords = *coords;
                 self.dirty = true;
             }
+            Message::PendingKeys(keys) => {
+                self.pending_keys = keys.clone();
+                self.dirty = true;
+            }
+            Message::Action(_)
+            | Message::RepeatAction(..)
+            | Message::Operator { .. } if !self.pending_keys.is_empty() => {
+                self.pending_keys.clear();
+                self.dirty = true;
+            }
             Message::LayerChanged { layer, total_layers } => {
                 self.layer = *layer;
                 self.total_layers = *total_layers;
                 self.dirty = true;
             }
+            Message::ConfigReloaded => {
+                if let Err(e) = self.reload_font(&ctx.config.ui, ctx.context) {
+                    return Message::CommandError(format!("failed to load the font: {}", e));
+                }
+            }
             Message::Input(_, _)
             | Message::CursorPos(_)
             | Message::Action(_)
+            | Message::RepeatAction(..)
+            | Message::Operator { .. }
             | Message::Command(_)
+            | Message::Candidates(_)
+            | Message::CommandError(_)
             | Message::ReloadPlugin(_)
+            | Message::Tick
             | Message::Noop => {}
         }
 
         Message::Noop
     }
 
-    fn render(&mut self, ctx: &mut MessageCtx) -> Result<()> {
+    fn render(&mut self, ctx: &mut MessageCtx) {
         if self.dirty {
             self.dirty = false;
-            self.update_text();
+            self.update_text(ctx.context);
         }
-        self.renderer.render(
-            self.text.texture(),
-            &self.text.vertex_data(),
-            &self.viewport,
-            ctx.context,
-        )?;
-        Ok(())
+
+        self.text.texture().bind();
+        self.renderer.render_instanced(ctx.context, 1);
     }
 }